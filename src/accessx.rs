@@ -0,0 +1,135 @@
+use std::collections::HashMap;
+use std::time::Duration;
+
+use fixedbitset::FixedBitSet;
+
+use keys::Key;
+
+/// Filters key events for users who press keys accidentally (Slow Keys and Bounce Keys)
+///
+/// The filter sits in front of KeyboardState. Every key press and release from the hardware
+/// should be passed to `feed`, and the events it returns passed on to `actuate_key`. Rejected
+/// presses (and their releases) are dropped, so they never reach `pressed_keys`.
+///
+/// Timestamps are supplied by the caller in the same way as `KeyRepeater`.
+#[derive(Debug, Clone)]
+pub struct AccessFilter {
+    /// When set, a key must be held for this long before its press is accepted (Slow Keys)
+    ///
+    /// default: None
+    pub slow_keys: Option<Duration>,
+
+    /// When set, pressing a key again within this long of releasing it is ignored (Bounce Keys)
+    ///
+    /// default: None
+    pub bounce_keys: Option<Duration>,
+
+    /// Keys whose press has been passed on
+    accepted: FixedBitSet,
+
+    /// Keys that are held but haven't been held long enough to be accepted, with the time they will be
+    pending: Vec<(Key, Duration)>,
+
+    /// The time each key was last released, by keycode
+    released_at: HashMap<u8, Duration>,
+}
+
+impl Default for AccessFilter {
+    fn default() -> AccessFilter {
+        AccessFilter::new()
+    }
+}
+
+impl AccessFilter {
+    /// Creates a new AccessFilter instance with both filters turned off
+    pub fn new() -> AccessFilter {
+        AccessFilter {
+            slow_keys: None,
+            bounce_keys: None,
+            accepted: FixedBitSet::with_capacity(256),
+            pending: Vec::new(),
+            released_at: HashMap::new(),
+        }
+    }
+
+    /// Returns the time that `tick` should next be called, or None if no key is waiting to be accepted
+    pub fn next_deadline(&self) -> Option<Duration> {
+        self.pending.iter().map(|&(_, deadline)| deadline).min()
+    }
+
+    /// Should be called whenever a key is pressed or released
+    ///
+    /// Returns the key events that should be passed to KeyboardState.
+    pub fn feed(&mut self, key: Key, pressed: bool, now: Duration) -> Vec<(Key, bool)> {
+        let keycode = key.keycode() as usize;
+        let pending = self.pending.iter().position(|&(pending, _)| pending == key);
+
+        if !pressed {
+            if let Some(index) = pending {
+                // Released before it was accepted
+                self.pending.remove(index);
+                return Vec::new();
+            }
+
+            if !self.accepted.contains(keycode) {
+                return Vec::new();
+            }
+
+            self.accepted.set(keycode, false);
+            self.released_at.insert(key.keycode(), now);
+            return vec![(key, false)];
+        }
+
+        // Hardware repeats of accepted keys are passed on as normal
+        if self.accepted.contains(keycode) {
+            return vec![(key, true)];
+        }
+
+        if let Some(index) = pending {
+            // A hardware repeat of a key that is still being held for slow keys
+            if self.pending[index].1 <= now {
+                self.pending.remove(index);
+                self.accepted.insert(keycode);
+                return vec![(key, true)];
+            }
+            return Vec::new();
+        }
+
+        if let (Some(bounce), Some(&released_at)) = (self.bounce_keys, self.released_at.get(&key.keycode())) {
+            if now < released_at + bounce {
+                return Vec::new();
+            }
+        }
+
+        match self.slow_keys {
+            Some(slow) if slow > Duration::from_millis(0) => {
+                self.pending.push((key, now + slow));
+                Vec::new()
+            }
+            _ => {
+                self.accepted.insert(keycode);
+                vec![(key, true)]
+            }
+        }
+    }
+
+    /// Accepts the keys that have now been held for long enough
+    ///
+    /// Returns the key events that should be passed to KeyboardState, in the order they were pressed.
+    pub fn tick(&mut self, now: Duration) -> Vec<(Key, bool)> {
+        let mut events = Vec::new();
+        let accepted = &mut self.accepted;
+
+        self.pending.retain(|&(key, deadline)| {
+            if deadline <= now {
+                accepted.insert(key.keycode() as usize);
+                events.push((key, true));
+                false
+            } else {
+                true
+            }
+        });
+
+        events
+    }
+}
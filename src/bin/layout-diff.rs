@@ -0,0 +1,70 @@
+//! Prints the differences between two keyboard layouts
+//!
+//! Usage: layout-diff <old layout> <new layout>
+//!        layout-diff --dump <layout>
+//!
+//! Each layout can be:
+//!  * A layout file (see `orbkb::layout::parse`), eg. "gb.layout"
+//!  * A built in layout, named by id ("gb"), id and variant ("de(nodeadkeys)") or locale ("en-GB")
+//!
+//! `--dump` prints a layout in the layout file format, so built in layouts can be saved and
+//! compared with later versions.
+//!
+//! Exits with 0 if the layouts are the same, 1 if they differ and 2 on error.
+
+extern crate orbkb;
+
+use std::env;
+use std::fs;
+use std::path::Path;
+use std::process;
+
+use orbkb::layout::{self, Layout, LayoutInfo, LayoutRegistry};
+
+fn exit_with_error(message: String) -> ! {
+    eprintln!("{}", message);
+    process::exit(2);
+}
+
+fn load(registry: &LayoutRegistry, name: &str) -> (LayoutInfo, Layout) {
+    if Path::new(name).is_file() {
+        let text = fs::read_to_string(name).unwrap_or_else(|err| exit_with_error(format!("Couldn't read '{}': {}", name, err)));
+        return layout::parse(&text).unwrap_or_else(|err| exit_with_error(format!("Couldn't parse '{}': {}", name, err)));
+    }
+
+    match registry.lookup(name) {
+        Some((info, layout)) => (info.clone(), layout.clone()),
+        None => {
+            let available: Vec<String> = registry.layouts().iter().map(|info| info.full_id()).collect();
+            exit_with_error(format!("Unknown layout '{}'. Available layouts: {}", name, available.join(", ")));
+        }
+    }
+}
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    let registry = LayoutRegistry::builtin();
+
+    if args.len() == 3 && args[1] == "--dump" {
+        let (info, layout) = load(&registry, &args[2]);
+        print!("{}", layout::to_text(&info, &layout));
+        return;
+    }
+
+    if args.len() != 3 {
+        eprintln!("Usage: {} <old layout> <new layout>", args[0]);
+        eprintln!("       {} --dump <layout>", args[0]);
+        process::exit(2);
+    }
+
+    let (old_info, old) = load(&registry, &args[1]);
+    let (new_info, new) = load(&registry, &args[2]);
+
+    let diff = layout::diff_with_info((&old_info, &old), (&new_info, &new));
+    if diff.is_empty() {
+        return;
+    }
+
+    print!("{}", diff);
+    process::exit(1);
+}
@@ -0,0 +1,152 @@
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+use fixedbitset::FixedBitSet;
+
+use event::KeyboardEvent;
+use keys::Key;
+use layout::Layout;
+use state::{KeyboardState, LockKeyState, Modifiers};
+
+/// Identifies a physical keyboard attached to `KeyboardDevices`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct DeviceId(pub u32);
+
+/// A KeyboardEvent, along with the device that caused it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeviceEvent {
+    pub device: DeviceId,
+    pub event: KeyboardEvent,
+}
+
+#[derive(Debug, Clone)]
+struct Device {
+    pressed_keys: FixedBitSet,
+    lock_keys: LockKeyState,
+}
+
+/// Merges several physical keyboards into one logical keyboard
+///
+/// Each device's pressed keys are tracked separately. A key is pressed in the merged state
+/// while any device holds it, so holding Shift on one keyboard affects keys typed on another,
+/// and unplugging a device only releases the keys that no other device holds.
+#[derive(Debug, Clone)]
+pub struct KeyboardDevices {
+    /// The merged state, which holds the layout and other settings for all the devices
+    pub state: KeyboardState,
+
+    /// When set to true, the lock keys are shared by all devices. Otherwise each device has its own
+    ///
+    /// default: true
+    pub shared_lock_keys: bool,
+
+    devices: BTreeMap<DeviceId, Device>,
+}
+
+impl KeyboardDevices {
+    /// Creates a new aggregator with no devices
+    pub fn new(layout: Layout) -> KeyboardDevices {
+        KeyboardDevices {
+            state: KeyboardState::new(layout),
+            shared_lock_keys: true,
+            devices: BTreeMap::new(),
+        }
+    }
+
+    /// Adds a device. Devices are also added when they first actuate a key
+    pub fn add_device(&mut self, device: DeviceId) {
+        // Devices with their own lock keys start with them all off
+        let lock_keys = if self.shared_lock_keys { self.state.lock_keys } else { LockKeyState::empty() };
+        self.devices.entry(device).or_insert_with(|| Device {
+            pressed_keys: FixedBitSet::with_capacity(256),
+            lock_keys,
+        });
+    }
+
+    /// Removes a device (eg. when it is unplugged)
+    ///
+    /// Returns a release event for each key the device held that no other device holds, after
+    /// remapping them by the state's `remap`.
+    pub fn remove_device(&mut self, device: DeviceId) -> Vec<DeviceEvent> {
+        let removed = match self.devices.remove(&device) {
+            Some(removed) => removed,
+            None => return Vec::new(),
+        };
+
+        let released: Vec<Key> = removed.pressed_keys.ones()
+            .filter(|&keycode| !self.devices.values().any(|other| other.pressed_keys.contains(keycode)))
+            .map(|keycode| Key::from_keycode(keycode as u8))
+            .collect();
+
+        self.state.release_physical_keys(&released).into_iter()
+            .map(|event| DeviceEvent { device, event })
+            .collect()
+    }
+
+    /// Returns the attached devices, in ascending order
+    pub fn devices(&self) -> Vec<DeviceId> {
+        self.devices.keys().cloned().collect()
+    }
+
+    /// Returns the keys held on a device, or None if the device isn't attached
+    pub fn pressed_keys(&self, device: DeviceId) -> Option<&FixedBitSet> {
+        self.devices.get(&device).map(|device| &device.pressed_keys)
+    }
+
+    /// Returns the lock keys of a device, which are the shared lock keys if shared_lock_keys is set
+    pub fn lock_keys(&self, device: DeviceId) -> Option<LockKeyState> {
+        if self.shared_lock_keys {
+            self.devices.get(&device).map(|_| self.state.lock_keys)
+        } else {
+            self.devices.get(&device).map(|device| device.lock_keys)
+        }
+    }
+
+    /// Returns the modifiers held on all the devices
+    pub fn modifiers(&self) -> Modifiers {
+        self.state.get_modifiers()
+    }
+
+    /// Should be called whenever a key is pressed or released on a device
+    ///
+    /// Returns None when another device holds the key, as it is already pressed in the merged
+    /// state. Pressing it doesn't press it again and releasing it doesn't release it. Auto-repeats
+    /// from a device that holds the key are still returned.
+    pub fn actuate_key(&mut self, device: DeviceId, key: Key, pressed: bool) -> Option<DeviceEvent> {
+        self.actuate_key_with_time(device, key, pressed, None)
+    }
+
+    /// Same as `actuate_key`, with the time that the key was pressed or released
+    pub fn actuate_key_at(&mut self, device: DeviceId, key: Key, pressed: bool, now: Duration) -> Option<DeviceEvent> {
+        self.actuate_key_with_time(device, key, pressed, Some(now))
+    }
+
+    fn actuate_key_with_time(&mut self, device: DeviceId, key: Key, pressed: bool, now: Option<Duration>) -> Option<DeviceEvent> {
+        self.add_device(device);
+
+        let keycode = key.keycode() as usize;
+        let held_elsewhere = self.devices.iter()
+            .any(|(&id, other)| id != device && other.pressed_keys.contains(keycode));
+
+        let shared_lock_keys = self.shared_lock_keys;
+        let entry = self.devices.get_mut(&device)?;
+        let held_here = entry.pressed_keys.contains(keycode);
+        entry.pressed_keys.set(keycode, pressed);
+
+        if held_elsewhere && !(pressed && held_here) {
+            return None;
+        }
+
+        // The merged state uses the lock keys of the device that is typing
+        if !shared_lock_keys {
+            self.state.lock_keys = entry.lock_keys;
+        }
+        let event = match now {
+            Some(now) => self.state.actuate_key_at(key, pressed, now),
+            None => self.state.actuate_key(key, pressed),
+        };
+        entry.lock_keys = self.state.lock_keys;
+
+        Some(DeviceEvent { device, event })
+    }
+}
@@ -1,5 +1,23 @@
+use std::time::Duration;
+
+use ime::Preedit;
 use keys::Key;
-use state::Modifiers;
+use state::{LockKeyState, Modifiers};
+
+/// Key combinations that are reserved for the operating system
+///
+/// These are reported in `KeyboardEvent::privileged` instead of as a logical event, so that they
+/// can be handled before (and never passed on to) applications.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PrivilegedEvent {
+    /// Ctrl+Alt+Delete was pressed (Secure Attention Key)
+    SecureAttention,
+
+    /// A key was pressed while Alt and SysRq (or Print Screen) are held (Magic SysRq)
+    ///
+    /// Contains the key, which selects the command (eg. KEY_B to reboot).
+    SysRq(Key),
+}
 
 /// Represents logical keyboard events
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -7,12 +25,54 @@ pub enum LogicalKeyboardEvent {
     /// A symbol has been inputted
     Symbol(char),
 
+    /// A symbol made of multiple characters has been inputted
+    ///
+    /// This is emitted instead of 'Symbol' by keys that type ligatures or grapheme
+    /// clusters (eg. "ij" in Dutch)
+    Text(String),
+
     /// A command has been run
     ///
     /// This event is triggered when either a command key (eg. enter, backspace, escape)
     /// is pressed or a command modifier (ctrl, alt, super) was used in conjunction
     /// with any other key. For example, Alt+Tab, Ctrl+c, Ctrl+Alt+Delete.
+    ///
+    /// The modifiers include the L_ and R_ flags of the sides that are held. To compare with
+    /// modifiers that don't say which side (eg. `Command(CTRL, KEY_C)`), use `side_agnostic`.
     Command(Modifiers, Key),
+
+    /// The text being composed by the input method has changed
+    Preedit(Preedit),
+
+    /// The input method has committed text
+    ///
+    /// Also contains the text that is still being composed, which is usually empty.
+    Commit { text: String, preedit: Preedit },
+
+    /// A modifier has been latched, locked or unlocked by sticky keys
+    ///
+    /// Contains the modifiers that are now latched and locked, including their L_ and R_ flags.
+    StickyModifiers { latched: Modifiers, locked: Modifiers },
+
+    /// Sticky keys has been turned on (true) or off (false) by the keyboard shortcut
+    StickyKeys(bool),
+}
+
+impl LogicalKeyboardEvent {
+    /// Returns the event with the L_ and R_ flags removed from its modifiers
+    ///
+    /// This is useful for matching commands when either side of a modifier is fine, eg.
+    /// `event.side_agnostic() == Command(CTRL, KEY_C)` matches both Ctrl keys.
+    pub fn side_agnostic(&self) -> LogicalKeyboardEvent {
+        match *self {
+            LogicalKeyboardEvent::Command(modifiers, key) => LogicalKeyboardEvent::Command(modifiers.side_agnostic(), key),
+            LogicalKeyboardEvent::StickyModifiers { latched, locked } => LogicalKeyboardEvent::StickyModifiers {
+                latched: latched.side_agnostic(),
+                locked: locked.side_agnostic(),
+            },
+            ref event => event.clone(),
+        }
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -31,4 +91,20 @@ pub struct KeyboardEvent {
     /// For example: Pressing an alphabetic key will create a 'Symbol' event and pressing
     /// 'Alt+Tab' together will create a 'Command' event.
     pub logical: Option<LogicalKeyboardEvent>,
+
+    /// The time the key was actuated, if it was given (eg. to `KeyboardState::actuate_key_at`)
+    pub timestamp: Option<Duration>,
+
+    /// For releases, how long the key was held, if the times of the press and release are known
+    pub held_for: Option<Duration>,
+
+    /// If this event toggled caps lock, num lock or scroll lock, the new state of all the lock keys
+    ///
+    /// The lock key still creates a 'Command' event in `logical`.
+    pub lock_keys: Option<LockKeyState>,
+
+    /// If this event pressed a key combination reserved for the operating system, details of it
+    ///
+    /// `logical` is None for these events, so that applications can't act on them.
+    pub privileged: Option<PrivilegedEvent>,
 }
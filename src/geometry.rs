@@ -0,0 +1,330 @@
+use keys::{self, Key};
+
+/// Allowance for rounding errors when comparing key edges
+const EPSILON: f32 = 0.01;
+
+/// The standard that the main block of the keyboard follows
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Standard {
+    /// American standard
+    ///
+    /// The enter key is one row high and KEY_HASH (which types '\' on the US layout)
+    /// sits above it, at the end of the top row.
+    Ansi,
+
+    /// European standard
+    ///
+    /// The enter key is two rows high and KEY_HASH (which types '#' on the GB layout)
+    /// sits to the left of it, at the end of the home row. There is an extra key
+    /// (KEY_B_SLASH) between the left shift key and 'Z'.
+    Iso,
+
+    /// Japanese standard
+    ///
+    /// Like ISO, but with the yen and ro keys instead of KEY_B_SLASH, a shorter backspace
+    /// and right shift, and the henkan, muhenkan and katakana/hiragana keys either side
+    /// of a shorter space bar.
+    Jis,
+}
+
+/// The size of the keyboard
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Size {
+    /// Function keys, navigation cluster and numpad (ANSI 104, ISO 105, JIS 109)
+    Full,
+
+    /// Function keys and navigation cluster, without the numpad
+    Tenkeyless,
+
+    /// Only the main block of the keyboard
+    SixtyPercent,
+}
+
+/// Where a key is on the keyboard
+///
+/// Positions and sizes are measured in keys, where 1.0 is the width of a letter key.
+/// Rows are counted from the top, starting at 0.
+///
+/// Some keys (the ISO enter key) are not rectangular. These are made of multiple parts, the
+/// first part is the main part of the key and each following part continues it on the row below.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct KeyPosition {
+    pub key: Key,
+    pub row: u8,
+    pub x: f32,
+    pub y: f32,
+    pub width: f32,
+    pub height: f32,
+}
+
+impl KeyPosition {
+    fn centre_x(&self) -> f32 {
+        self.x + self.width / 2.0
+    }
+
+    fn centre_y(&self) -> f32 {
+        self.y + self.height / 2.0
+    }
+
+    fn right(&self) -> f32 {
+        self.x + self.width
+    }
+
+    fn bottom(&self) -> f32 {
+        self.y + self.height
+    }
+}
+
+/// Describes where each key is on a physical keyboard
+#[derive(Debug, Clone, PartialEq)]
+pub struct Geometry {
+    pub standard: Standard,
+    pub size: Size,
+
+    /// The position of every key, row by row
+    pub keys: Vec<KeyPosition>,
+}
+
+struct Builder {
+    keys: Vec<KeyPosition>,
+}
+
+impl Builder {
+    /// Adds a row of keys one unit high, starting at x
+    fn row(&mut self, row: u8, y: f32, x: f32, keys: &[(Key, f32)]) {
+        let mut x = x;
+        for &(key, width) in keys {
+            self.key(row, y, x, key, width, 1.0);
+            x += width;
+        }
+    }
+
+    fn key(&mut self, row: u8, y: f32, x: f32, key: Key, width: f32, height: f32) {
+        self.keys.push(KeyPosition { key, row, x, y, width, height });
+    }
+
+    fn main_block(&mut self, standard: Standard, first_row: u8, y: f32) {
+        let mut number_row = vec![
+            (keys::KEY_BACKTICK, 1.0), (keys::KEY_1, 1.0), (keys::KEY_2, 1.0), (keys::KEY_3, 1.0),
+            (keys::KEY_4, 1.0), (keys::KEY_5, 1.0), (keys::KEY_6, 1.0), (keys::KEY_7, 1.0),
+            (keys::KEY_8, 1.0), (keys::KEY_9, 1.0), (keys::KEY_0, 1.0), (keys::KEY_HYPHEN, 1.0),
+            (keys::KEY_EQUALS, 1.0),
+        ];
+        match standard {
+            Standard::Ansi | Standard::Iso => number_row.push((keys::KEY_BACKSPACE, 2.0)),
+            Standard::Jis => number_row.extend_from_slice(&[(keys::KEY_YEN, 1.0), (keys::KEY_BACKSPACE, 1.0)]),
+        }
+        self.row(first_row, y, 0.0, &number_row);
+
+        let mut top_row = vec![
+            (keys::KEY_TAB, 1.5), (keys::KEY_Q, 1.0), (keys::KEY_W, 1.0), (keys::KEY_E, 1.0),
+            (keys::KEY_R, 1.0), (keys::KEY_T, 1.0), (keys::KEY_Y, 1.0), (keys::KEY_U, 1.0),
+            (keys::KEY_I, 1.0), (keys::KEY_O, 1.0), (keys::KEY_P, 1.0), (keys::KEY_OP_BRACKET, 1.0),
+            (keys::KEY_CL_BRACKET, 1.0),
+        ];
+        match standard {
+            Standard::Ansi => top_row.push((keys::KEY_HASH, 1.5)),
+            Standard::Iso | Standard::Jis => top_row.push((keys::KEY_ENTER, 1.5)),
+        }
+        self.row(first_row + 1, y + 1.0, 0.0, &top_row);
+
+        let mut home_row = vec![
+            (keys::KEY_CAPS_LOCK, 1.75), (keys::KEY_A, 1.0), (keys::KEY_S, 1.0), (keys::KEY_D, 1.0),
+            (keys::KEY_F, 1.0), (keys::KEY_G, 1.0), (keys::KEY_H, 1.0), (keys::KEY_J, 1.0),
+            (keys::KEY_K, 1.0), (keys::KEY_L, 1.0), (keys::KEY_COLON, 1.0), (keys::KEY_QUOTE, 1.0),
+        ];
+        match standard {
+            Standard::Ansi => home_row.push((keys::KEY_ENTER, 2.25)),
+            Standard::Iso | Standard::Jis => home_row.extend_from_slice(&[(keys::KEY_HASH, 1.0), (keys::KEY_ENTER, 1.25)]),
+        }
+        self.row(first_row + 2, y + 2.0, 0.0, &home_row);
+
+        let letters = [
+            (keys::KEY_Z, 1.0), (keys::KEY_X, 1.0), (keys::KEY_C, 1.0), (keys::KEY_V, 1.0),
+            (keys::KEY_B, 1.0), (keys::KEY_N, 1.0), (keys::KEY_M, 1.0), (keys::KEY_COMMA, 1.0),
+            (keys::KEY_PERIOD, 1.0), (keys::KEY_F_SLASH, 1.0),
+        ];
+        let mut bottom_row = Vec::new();
+        match standard {
+            Standard::Ansi => {
+                bottom_row.push((keys::KEY_L_SHIFT, 2.25));
+                bottom_row.extend_from_slice(&letters);
+                bottom_row.push((keys::KEY_R_SHIFT, 2.75));
+            }
+            Standard::Iso => {
+                bottom_row.extend_from_slice(&[(keys::KEY_L_SHIFT, 1.25), (keys::KEY_B_SLASH, 1.0)]);
+                bottom_row.extend_from_slice(&letters);
+                bottom_row.push((keys::KEY_R_SHIFT, 2.75));
+            }
+            Standard::Jis => {
+                bottom_row.push((keys::KEY_L_SHIFT, 2.25));
+                bottom_row.extend_from_slice(&letters);
+                bottom_row.extend_from_slice(&[(keys::KEY_RO, 1.0), (keys::KEY_R_SHIFT, 1.75)]);
+            }
+        }
+        self.row(first_row + 3, y + 3.0, 0.0, &bottom_row);
+
+        let space_row: &[(Key, f32)] = match standard {
+            Standard::Ansi | Standard::Iso => &[
+                (keys::KEY_L_CTRL, 1.25), (keys::KEY_L_SUPER, 1.25), (keys::KEY_ALT, 1.25),
+                (keys::KEY_SPACE, 6.25), (keys::KEY_ALT_GR, 1.25), (keys::KEY_R_SUPER, 1.25),
+                (keys::KEY_MENU, 1.25), (keys::KEY_R_CTRL, 1.25),
+            ],
+            Standard::Jis => &[
+                (keys::KEY_L_CTRL, 1.25), (keys::KEY_L_SUPER, 1.25), (keys::KEY_ALT, 1.25),
+                (keys::KEY_MUHENKAN, 1.25), (keys::KEY_SPACE, 2.5), (keys::KEY_HENKAN, 1.25),
+                (keys::KEY_KATAKANA_HIRAGANA, 1.25), (keys::KEY_ALT_GR, 1.25), (keys::KEY_R_SUPER, 1.25),
+                (keys::KEY_MENU, 1.25), (keys::KEY_R_CTRL, 1.25),
+            ],
+        };
+        self.row(first_row + 4, y + 4.0, 0.0, space_row);
+    }
+
+    fn function_row(&mut self) {
+        self.row(0, 0.0, 0.0, &[(keys::KEY_ESC, 1.0)]);
+        self.row(0, 0.0, 2.0, &[(keys::KEY_F1, 1.0), (keys::KEY_F2, 1.0), (keys::KEY_F3, 1.0), (keys::KEY_F4, 1.0)]);
+        self.row(0, 0.0, 6.5, &[(keys::KEY_F5, 1.0), (keys::KEY_F6, 1.0), (keys::KEY_F7, 1.0), (keys::KEY_F8, 1.0)]);
+        self.row(0, 0.0, 11.0, &[(keys::KEY_F9, 1.0), (keys::KEY_F10, 1.0), (keys::KEY_F11, 1.0), (keys::KEY_F12, 1.0)]);
+        self.row(0, 0.0, 15.25, &[(keys::KEY_PRT_SCR, 1.0), (keys::KEY_SCROLL_LOCK, 1.0), (keys::KEY_PAUSE, 1.0)]);
+    }
+
+    fn navigation_cluster(&mut self) {
+        self.row(1, 1.5, 15.25, &[(keys::KEY_INS, 1.0), (keys::KEY_HOME, 1.0), (keys::KEY_PG_UP, 1.0)]);
+        self.row(2, 2.5, 15.25, &[(keys::KEY_DEL, 1.0), (keys::KEY_END, 1.0), (keys::KEY_PG_DOWN, 1.0)]);
+        self.row(4, 4.5, 16.25, &[(keys::KEY_UP, 1.0)]);
+        self.row(5, 5.5, 15.25, &[(keys::KEY_LEFT, 1.0), (keys::KEY_DOWN, 1.0), (keys::KEY_RIGHT, 1.0)]);
+    }
+
+    fn numpad(&mut self) {
+        self.row(1, 1.5, 18.5, &[(keys::KEY_NUM_LOCK, 1.0), (keys::KEY_NUM_DIV, 1.0), (keys::KEY_NUM_MUL, 1.0), (keys::KEY_NUM_SUB, 1.0)]);
+        self.row(2, 2.5, 18.5, &[(keys::KEY_NUM_7, 1.0), (keys::KEY_NUM_8, 1.0), (keys::KEY_NUM_9, 1.0)]);
+        self.key(2, 2.5, 21.5, keys::KEY_NUM_ADD, 1.0, 2.0);
+        self.row(3, 3.5, 18.5, &[(keys::KEY_NUM_4, 1.0), (keys::KEY_NUM_5, 1.0), (keys::KEY_NUM_6, 1.0)]);
+        self.row(4, 4.5, 18.5, &[(keys::KEY_NUM_1, 1.0), (keys::KEY_NUM_2, 1.0), (keys::KEY_NUM_3, 1.0)]);
+        self.key(4, 4.5, 21.5, keys::KEY_NUM_ENTER, 1.0, 2.0);
+        self.row(5, 5.5, 18.5, &[(keys::KEY_NUM_0, 2.0), (keys::KEY_NUM_DECIMAL, 1.0)]);
+    }
+}
+
+impl Geometry {
+    /// Creates the geometry of a keyboard of the specified standard and size
+    pub fn new(standard: Standard, size: Size) -> Geometry {
+        let mut builder = Builder { keys: Vec::new() };
+
+        match size {
+            Size::Full | Size::Tenkeyless => {
+                // The function keys are separated from the rest of the keyboard by a half-row gap
+                builder.function_row();
+                builder.main_block(standard, 1, 1.5);
+                builder.navigation_cluster();
+                if size == Size::Full {
+                    builder.numpad();
+                }
+            }
+            Size::SixtyPercent => builder.main_block(standard, 0, 0.0),
+        }
+
+        // Sort row by row so the keys are read left to right, top to bottom
+        builder.keys.sort_by(|a, b| a.row.cmp(&b.row).then(a.x.partial_cmp(&b.x).unwrap()));
+
+        Geometry {
+            standard,
+            size,
+            keys: builder.keys,
+        }
+    }
+
+    /// The full size American keyboard
+    pub fn ansi_104() -> Geometry {
+        Geometry::new(Standard::Ansi, Size::Full)
+    }
+
+    /// The full size European keyboard
+    pub fn iso_105() -> Geometry {
+        Geometry::new(Standard::Iso, Size::Full)
+    }
+
+    /// The full size Japanese keyboard
+    pub fn jis_109() -> Geometry {
+        Geometry::new(Standard::Jis, Size::Full)
+    }
+
+    /// Returns the position of the key, or None if the keyboard doesn't have it
+    ///
+    /// For keys that are made of multiple parts, this returns the main part.
+    pub fn position(&self, key: Key) -> Option<&KeyPosition> {
+        self.keys.iter().find(|position| position.key == key)
+    }
+
+    /// Returns true if the keyboard has the specified key
+    pub fn has_key(&self, key: Key) -> bool {
+        self.position(key).is_some()
+    }
+
+    /// Returns the width of the keyboard, in keys
+    pub fn width(&self) -> f32 {
+        self.keys.iter().map(|position| position.right()).fold(0.0, f32::max)
+    }
+
+    /// Returns the height of the keyboard, in keys
+    pub fn height(&self) -> f32 {
+        self.keys.iter().map(|position| position.bottom()).fold(0.0, f32::max)
+    }
+
+    /// Returns the number of rows on the keyboard
+    pub fn rows(&self) -> u8 {
+        self.keys.iter().map(|position| position.row + 1).max().unwrap_or(0)
+    }
+
+    /// Finds the nearest key in a direction, measured from the middle of the specified key
+    ///
+    /// `is_candidate` selects the parts that are on the correct side of the key, and `distance`
+    /// measures the gap between them and the key.
+    fn find_nearest<C, D>(&self, key: Key, is_candidate: C, distance: D) -> Option<Key>
+        where C: Fn(&KeyPosition, &KeyPosition) -> bool, D: Fn(&KeyPosition, &KeyPosition) -> f32
+    {
+        let from = self.position(key)?;
+
+        self.keys.iter()
+            .filter(|other| other.key != key && is_candidate(from, other))
+            .map(|other| (other.key, distance(from, other)))
+            .min_by(|a, b| a.1.partial_cmp(&b.1).unwrap())
+            .map(|(key, _)| key)
+    }
+
+    /// Returns the key directly above the specified key
+    pub fn key_above(&self, key: Key) -> Option<Key> {
+        self.find_nearest(
+            key,
+            |from, other| other.bottom() <= from.y + EPSILON && other.x <= from.centre_x() && from.centre_x() < other.right(),
+            |from, other| from.y - other.bottom(),
+        )
+    }
+
+    /// Returns the key directly below the specified key
+    pub fn key_below(&self, key: Key) -> Option<Key> {
+        self.find_nearest(
+            key,
+            |from, other| other.y >= from.bottom() - EPSILON && other.x <= from.centre_x() && from.centre_x() < other.right(),
+            |from, other| other.y - from.bottom(),
+        )
+    }
+
+    /// Returns the key directly to the left of the specified key
+    pub fn key_left_of(&self, key: Key) -> Option<Key> {
+        self.find_nearest(
+            key,
+            |from, other| other.right() <= from.x + EPSILON && other.y <= from.centre_y() && from.centre_y() < other.bottom(),
+            |from, other| from.x - other.right(),
+        )
+    }
+
+    /// Returns the key directly to the right of the specified key
+    pub fn key_right_of(&self, key: Key) -> Option<Key> {
+        self.find_nearest(
+            key,
+            |from, other| other.x >= from.right() - EPSILON && other.y <= from.centre_y() && from.centre_y() < other.bottom(),
+            |from, other| other.x - from.right(),
+        )
+    }
+}
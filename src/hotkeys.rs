@@ -0,0 +1,333 @@
+use std::error::Error;
+use std::fmt;
+use std::time::Duration;
+
+use event::{KeyboardEvent, LogicalKeyboardEvent};
+use keys::{self, Key};
+use state::{self, Modifiers};
+
+/// A key pressed while holding some modifiers (eg. Ctrl+S)
+///
+/// A modifier without a side (eg. CTRL) matches either side. A modifier with a side (eg. CTRL | R_CTRL)
+/// only matches that side.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Chord {
+    pub modifiers: Modifiers,
+    pub key: Key,
+}
+
+impl Chord {
+    pub fn new(modifiers: Modifiers, key: Key) -> Chord {
+        Chord { modifiers, key }
+    }
+
+    /// Parses a chord such as "Ctrl+Alt+T", "RCtrl+F1" or "Ctrl+Caps Lock"
+    ///
+    /// Modifier and key names are not case sensitive. Keys are named as in `Key::from_name`, and
+    /// "+" on its own is the numpad plus key (eg. "Ctrl++"). Modifiers can be given a side with
+    /// an L or R prefix.
+    pub fn parse(text: &str) -> Result<Chord, HotkeyError> {
+        let error = || HotkeyError::Parse(text.to_string());
+        let text = text.trim();
+
+        // The key is after the last '+', unless the key name itself ends with '+'
+        let (modifier_text, key) = match text.strip_suffix('+').unwrap_or(text).rfind('+') {
+            Some(index) => (&text[..index], &text[index + 1..]),
+            None => ("", text),
+        };
+        let key = key.trim();
+        if key.is_empty() {
+            return Err(error());
+        }
+        let parts: Vec<&str> = if modifier_text.is_empty() {
+            Vec::new()
+        } else {
+            modifier_text.split('+').map(|part| part.trim()).collect()
+        };
+
+        let mut modifiers = Modifiers::empty();
+        for part in parts {
+            modifiers |= match part.to_lowercase().as_str() {
+                "ctrl" | "control" => state::CTRL,
+                "lctrl" => state::CTRL | state::L_CTRL,
+                "rctrl" => state::CTRL | state::R_CTRL,
+                "shift" => state::SHIFT,
+                "lshift" => state::SHIFT | state::L_SHIFT,
+                "rshift" => state::SHIFT | state::R_SHIFT,
+                "alt" => state::ALT,
+                "lalt" => state::ALT | state::L_ALT,
+                "ralt" => state::ALT | state::R_ALT,
+                "altgr" | "alt gr" => state::ALT_GR,
+                "super" | "win" | "cmd" => state::SUPER,
+                "lsuper" => state::SUPER | state::L_SUPER,
+                "rsuper" => state::SUPER | state::R_SUPER,
+                _ => return Err(error()),
+            };
+        }
+
+        let key = match key {
+            "+" => keys::KEY_NUM_ADD,
+            _ => Key::from_name(key).ok_or_else(error)?,
+        };
+        Ok(Chord::new(modifiers, key))
+    }
+
+    /// Returns true if pressing the key with the specified modifiers would trigger this chord
+    pub fn matches(&self, modifiers: Modifiers, key: Key) -> bool {
+        self.key == key
+            && self.modifiers.side_agnostic() == modifiers.side_agnostic()
+            && modifiers.contains(self.modifiers.sides())
+    }
+
+    /// Returns true if a key press could trigger both chords
+    pub fn overlaps(&self, other: &Chord) -> bool {
+        other.matches(self.modifiers, self.key) || self.matches(other.modifiers, other.key)
+    }
+}
+
+/// Returns true if the chords of `sequence` could trigger the start of `chords`
+fn overlaps_start(chords: &[Chord], sequence: &[Chord]) -> bool {
+    chords.len() >= sequence.len() && chords.iter().zip(sequence).all(|(a, b)| a.overlaps(b))
+}
+
+/// Returns true if the pressed chords match the start of `chords`
+fn matches_start(chords: &[Chord], pressed: &[Chord]) -> bool {
+    chords.len() >= pressed.len() && chords.iter().zip(pressed).all(|(chord, pressed)| chord.matches(pressed.modifiers, pressed.key))
+}
+
+impl fmt::Display for Chord {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let names = [
+            (state::CTRL, state::L_CTRL, state::R_CTRL, "Ctrl"),
+            (state::ALT, state::L_ALT, state::R_ALT, "Alt"),
+            (state::ALT_GR, Modifiers::empty(), Modifiers::empty(), "AltGr"),
+            (state::SHIFT, state::L_SHIFT, state::R_SHIFT, "Shift"),
+            (state::SUPER, state::L_SUPER, state::R_SUPER, "Super"),
+        ];
+        for &(modifier, left, right, name) in &names {
+            if !self.modifiers.contains(modifier) {
+                continue;
+            }
+            match (self.modifiers.contains(left) && !left.is_empty(), self.modifiers.contains(right) && !right.is_empty()) {
+                (true, false) => write!(f, "L{}+", name)?,
+                (false, true) => write!(f, "R{}+", name)?,
+                _ => write!(f, "{}+", name)?,
+            }
+        }
+        write!(f, "{}", self.key.name())
+    }
+}
+
+/// Identifies a binding in a `HotkeyRegistry`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct BindingId(pub usize);
+
+/// An error from registering a hotkey
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HotkeyError {
+    /// The hotkey could not be parsed
+    Parse(String),
+
+    /// The same hotkey is already registered
+    Conflict(BindingId),
+
+    /// The hotkey starts with another registered hotkey, or another registered hotkey starts with it
+    Ambiguous(BindingId),
+}
+
+impl fmt::Display for HotkeyError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            HotkeyError::Parse(ref text) => write!(f, "invalid hotkey {:?}", text),
+            HotkeyError::Conflict(id) => write!(f, "hotkey is already registered as binding {}", id.0),
+            HotkeyError::Ambiguous(id) => write!(f, "hotkey is ambiguous with binding {}", id.0),
+        }
+    }
+}
+
+impl Error for HotkeyError {}
+
+/// The result of feeding a key to a `HotkeyRegistry`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HotkeyMatch {
+    /// The key is not part of any hotkey
+    None,
+
+    /// The key is part of a sequence that hasn't been completed yet
+    Partial,
+
+    /// The key completed a hotkey
+    Matched(BindingId),
+}
+
+/// A list of hotkeys, which can be single chords ("Ctrl+Alt+T") or sequences ("Ctrl+X Ctrl+S")
+///
+/// Each Command event should be passed to `feed` (or every event to `feed_event`). If the next
+/// chord of a sequence isn't pressed within `timeout`, the sequence is abandoned.
+///
+/// Timestamps are supplied by the caller in the same way as `KeyRepeater`.
+#[derive(Debug, Clone)]
+pub struct HotkeyRegistry {
+    /// How long to wait for the next chord of a sequence
+    ///
+    /// default: 1s
+    pub timeout: Duration,
+
+    bindings: Vec<(BindingId, Vec<Chord>)>,
+    next_id: usize,
+
+    /// The chords of the sequence that have been pressed so far, and the time of the last one
+    progress: Vec<Chord>,
+    last_chord: Duration,
+}
+
+impl Default for HotkeyRegistry {
+    fn default() -> HotkeyRegistry {
+        HotkeyRegistry::new()
+    }
+}
+
+impl HotkeyRegistry {
+    /// Creates an empty registry
+    pub fn new() -> HotkeyRegistry {
+        HotkeyRegistry {
+            timeout: Duration::from_millis(1000),
+            bindings: Vec::new(),
+            next_id: 0,
+            progress: Vec::new(),
+            last_chord: Duration::from_millis(0),
+        }
+    }
+
+    /// Parses a hotkey, made of chords separated by spaces (eg. "Ctrl+X Ctrl+S")
+    ///
+    /// Key names can also contain spaces (eg. "Ctrl+Caps Lock"), so each chord is the longest run
+    /// of words that can be parsed as one.
+    pub fn parse(text: &str) -> Result<Vec<Chord>, HotkeyError> {
+        let words: Vec<&str> = text.split_whitespace().collect();
+
+        let mut chords = Vec::new();
+        let mut start = 0;
+        while start < words.len() {
+            let (end, chord) = (start + 1..=words.len()).rev()
+                .filter_map(|end| Chord::parse(&words[start..end].join(" ")).ok().map(|chord| (end, chord)))
+                .next()
+                .ok_or_else(|| HotkeyError::Parse(text.to_string()))?;
+            chords.push(chord);
+            start = end;
+        }
+
+        if chords.is_empty() {
+            return Err(HotkeyError::Parse(text.to_string()));
+        }
+        Ok(chords)
+    }
+
+    /// Parses and registers a hotkey
+    pub fn register(&mut self, text: &str) -> Result<BindingId, HotkeyError> {
+        let chords = HotkeyRegistry::parse(text)?;
+        self.register_chords(chords)
+    }
+
+    /// Registers a hotkey
+    ///
+    /// Fails if a registered hotkey could be triggered by the same keys (eg. "Ctrl+S" and
+    /// "RCtrl+S"), or if one of the hotkeys is the start of the other (as it wouldn't be possible
+    /// to tell when the shorter one was complete).
+    pub fn register_chords(&mut self, chords: Vec<Chord>) -> Result<BindingId, HotkeyError> {
+        for (id, existing) in &self.bindings {
+            if existing.len() == chords.len() && overlaps_start(existing, &chords) {
+                return Err(HotkeyError::Conflict(*id));
+            }
+            if overlaps_start(existing, &chords) || overlaps_start(&chords, existing) {
+                return Err(HotkeyError::Ambiguous(*id));
+            }
+        }
+
+        let id = BindingId(self.next_id);
+        self.next_id += 1;
+        self.bindings.push((id, chords));
+        Ok(id)
+    }
+
+    /// Removes a binding. Returns false if it wasn't registered
+    pub fn unregister(&mut self, id: BindingId) -> bool {
+        let count = self.bindings.len();
+        self.bindings.retain(|&(existing, _)| existing != id);
+        self.progress.clear();
+        self.bindings.len() != count
+    }
+
+    /// Returns the chords of a binding
+    pub fn chords(&self, id: BindingId) -> Option<&[Chord]> {
+        self.bindings.iter()
+            .find(|&&(existing, _)| existing == id)
+            .map(|(_, chords)| chords.as_slice())
+    }
+
+    /// Returns true if part of a sequence has been pressed
+    pub fn is_partial(&self) -> bool {
+        !self.progress.is_empty()
+    }
+
+    /// Returns the time that the current sequence will be abandoned, or None if there isn't one
+    pub fn next_deadline(&self) -> Option<Duration> {
+        if self.is_partial() {
+            Some(self.last_chord + self.timeout)
+        } else {
+            None
+        }
+    }
+
+    /// Abandons the current sequence
+    pub fn reset(&mut self) {
+        self.progress.clear();
+    }
+
+    /// Should be called whenever a key is pressed with the modifiers that are held
+    ///
+    /// The modifiers should include the L_ and R_ flags, as returned by `KeyboardState::get_modifiers`.
+    /// Modifier keys are ignored, so holding Ctrl between the chords of a sequence is fine.
+    pub fn feed(&mut self, modifiers: Modifiers, key: Key, now: Duration) -> HotkeyMatch {
+        if key.is_modifier() {
+            return if self.is_partial() { HotkeyMatch::Partial } else { HotkeyMatch::None };
+        }
+
+        if matches!(self.next_deadline(), Some(deadline) if now > deadline) {
+            self.progress.clear();
+        }
+
+        let started = self.is_partial();
+        self.progress.push(Chord::new(modifiers, key));
+        self.last_chord = now;
+
+        let progress = &self.progress;
+        let found = self.bindings.iter()
+            .find(|(_, chords)| chords.len() == progress.len() && matches_start(chords, progress));
+        if let Some(&(id, _)) = found {
+            self.progress.clear();
+            return HotkeyMatch::Matched(id);
+        }
+
+        if self.bindings.iter().any(|(_, chords)| matches_start(chords, &self.progress)) {
+            return HotkeyMatch::Partial;
+        }
+
+        self.progress.clear();
+
+        // The chord that broke the sequence may start another one
+        if started {
+            self.feed(modifiers, key, now)
+        } else {
+            HotkeyMatch::None
+        }
+    }
+
+    /// Feeds the Command from a KeyboardEvent, if it has one
+    pub fn feed_event(&mut self, event: &KeyboardEvent, now: Duration) -> HotkeyMatch {
+        match event.logical {
+            Some(LogicalKeyboardEvent::Command(modifiers, key)) if !event.repeat => self.feed(modifiers, key, now),
+            _ => HotkeyMatch::None,
+        }
+    }
+}
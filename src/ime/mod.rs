@@ -0,0 +1,71 @@
+use std::fmt;
+
+use event::LogicalKeyboardEvent;
+use keys::Key;
+
+/// The text that is being composed by an input method, before it is committed
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Preedit {
+    /// The text being composed (eg. "nihao")
+    pub text: String,
+
+    /// The position of the cursor in text, in chars
+    pub cursor: usize,
+
+    /// The texts that the composed text could be converted to (eg. "你好")
+    pub candidates: Vec<String>,
+
+    /// The candidate that will be committed, if there are any
+    pub selected: Option<usize>,
+}
+
+impl Preedit {
+    /// Returns true if nothing is being composed
+    pub fn is_empty(&self) -> bool {
+        self.text.is_empty()
+    }
+}
+
+/// What KeyboardState should do with a key after the input method has seen it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum InputMethodAction {
+    /// The input method didn't use the key, so it is handled as usual
+    Pass,
+
+    /// The input method used the key to change the preedit
+    Preedit,
+
+    /// The input method used the key and committed text
+    Commit(String),
+}
+
+/// Converts key presses into text, for languages with more symbols than keys (IME)
+///
+/// When an input method is set on KeyboardState, each key press is passed to `key_press` along
+/// with the logical event KeyboardState would emit without it. Keys that the input method uses
+/// emit a `Preedit` or `Commit` logical event instead.
+pub trait InputMethod: fmt::Debug {
+    /// Handles a key press (or repeat)
+    fn key_press(&mut self, key: Key, logical: Option<&LogicalKeyboardEvent>) -> InputMethodAction;
+
+    /// Returns the text that is being composed
+    fn preedit(&self) -> Preedit;
+
+    /// Abandons the text that is being composed
+    fn reset(&mut self);
+
+    /// Returns a copy of the input method, so KeyboardState can be cloned
+    fn clone_box(&self) -> Box<dyn InputMethod>;
+}
+
+impl Clone for Box<dyn InputMethod> {
+    fn clone(&self) -> Box<dyn InputMethod> {
+        self.clone_box()
+    }
+}
+
+mod romaji;
+mod table;
+
+pub use self::romaji::RomajiInputMethod;
+pub use self::table::TableInputMethod;
@@ -0,0 +1,251 @@
+use event::LogicalKeyboardEvent;
+use ime::{InputMethod, InputMethodAction, Preedit};
+use keys::{self, Key};
+
+/// Romaji (Hepburn, with some Nihon-shiki spellings) and the hiragana they convert to
+///
+/// Letters that don't start any of these are typed as they are, so each consonant needs an entry
+/// for every vowel it can be typed with.
+const ROMAJI: &[(&str, &str)] = &[
+    ("a", "あ"), ("i", "い"), ("u", "う"), ("e", "え"), ("o", "お"),
+    ("ka", "か"), ("ki", "き"), ("ku", "く"), ("ke", "け"), ("ko", "こ"),
+    ("sa", "さ"), ("shi", "し"), ("si", "し"), ("su", "す"), ("se", "せ"), ("so", "そ"),
+    ("ta", "た"), ("chi", "ち"), ("tsu", "つ"), ("tu", "つ"), ("te", "て"), ("to", "と"),
+    ("na", "な"), ("ni", "に"), ("nu", "ぬ"), ("ne", "ね"), ("no", "の"),
+    ("ha", "は"), ("hi", "ひ"), ("fu", "ふ"), ("hu", "ふ"), ("he", "へ"), ("ho", "ほ"),
+    ("ma", "ま"), ("mi", "み"), ("mu", "む"), ("me", "め"), ("mo", "も"),
+    ("ya", "や"), ("yu", "ゆ"), ("yo", "よ"),
+    ("ra", "ら"), ("ri", "り"), ("ru", "る"), ("re", "れ"), ("ro", "ろ"),
+    ("wa", "わ"), ("wo", "を"),
+    ("ga", "が"), ("gi", "ぎ"), ("gu", "ぐ"), ("ge", "げ"), ("go", "ご"),
+    ("za", "ざ"), ("ji", "じ"), ("zi", "じ"), ("zu", "ず"), ("ze", "ぜ"), ("zo", "ぞ"),
+    ("da", "だ"), ("du", "づ"), ("de", "で"), ("do", "ど"),
+    ("ba", "ば"), ("bi", "び"), ("bu", "ぶ"), ("be", "べ"), ("bo", "ぼ"),
+    ("pa", "ぱ"), ("pi", "ぴ"), ("pu", "ぷ"), ("pe", "ぺ"), ("po", "ぽ"),
+    ("kya", "きゃ"), ("kyu", "きゅ"), ("kyo", "きょ"),
+    ("sha", "しゃ"), ("shu", "しゅ"), ("sho", "しょ"), ("sya", "しゃ"), ("syu", "しゅ"), ("syo", "しょ"),
+    ("cha", "ちゃ"), ("chu", "ちゅ"), ("cho", "ちょ"), ("tya", "ちゃ"), ("tyu", "ちゅ"), ("tyo", "ちょ"),
+    ("nya", "にゃ"), ("nyu", "にゅ"), ("nyo", "にょ"),
+    ("hya", "ひゃ"), ("hyu", "ひゅ"), ("hyo", "ひょ"),
+    ("mya", "みゃ"), ("myu", "みゅ"), ("myo", "みょ"),
+    ("rya", "りゃ"), ("ryu", "りゅ"), ("ryo", "りょ"),
+    ("gya", "ぎゃ"), ("gyu", "ぎゅ"), ("gyo", "ぎょ"),
+    ("ja", "じゃ"), ("ju", "じゅ"), ("jo", "じょ"), ("jya", "じゃ"), ("jyu", "じゅ"), ("jyo", "じょ"),
+    ("bya", "びゃ"), ("byu", "びゅ"), ("byo", "びょ"),
+    ("pya", "ぴゃ"), ("pyu", "ぴゅ"), ("pyo", "ぴょ"),
+    // Extended kana, for loanwords
+    ("fa", "ふぁ"), ("fi", "ふぃ"), ("fe", "ふぇ"), ("fo", "ふぉ"),
+    ("she", "しぇ"), ("che", "ちぇ"), ("je", "じぇ"), ("ti", "てぃ"), ("di", "でぃ"),
+    ("wi", "うぃ"), ("we", "うぇ"),
+    ("va", "ゔぁ"), ("vi", "ゔぃ"), ("vu", "ゔ"), ("ve", "ゔぇ"), ("vo", "ゔぉ"),
+    // Small kana, typed on their own
+    ("xa", "ぁ"), ("xi", "ぃ"), ("xu", "ぅ"), ("xe", "ぇ"), ("xo", "ぉ"),
+    ("la", "ぁ"), ("li", "ぃ"), ("lu", "ぅ"), ("le", "ぇ"), ("lo", "ぉ"),
+    ("xya", "ゃ"), ("xyu", "ゅ"), ("xyo", "ょ"), ("lya", "ゃ"), ("lyu", "ゅ"), ("lyo", "ょ"),
+    ("xtsu", "っ"), ("xtu", "っ"), ("ltsu", "っ"), ("ltu", "っ"),
+];
+
+fn is_vowel(c: char) -> bool {
+    "aiueo".contains(c)
+}
+
+/// Converts hiragana to katakana, leaving other characters as they are
+fn to_katakana(text: &str) -> String {
+    text.chars()
+        .map(|c| match c {
+            'ぁ' ..= 'ゖ' => ::std::char::from_u32(c as u32 + 0x60).unwrap_or(c),
+            _ => c,
+        })
+        .collect()
+}
+
+/// An input method that converts romaji to hiragana or katakana as it is typed
+///
+/// Kana are committed as soon as the romaji for them is complete, and the preedit holds the
+/// romaji that hasn't been converted yet. A double consonant types a small tsu (sokuon, eg.
+/// "kitte" is きって), and "nn", "n'" or n before a consonant types ん. "-" types the long
+/// vowel mark ー.
+///
+/// Henkan turns conversion on and Muhenkan turns it off, and Katakana/Hiragana switches between
+/// hiragana and katakana (turning conversion on).
+#[derive(Debug, Clone)]
+pub struct RomajiInputMethod {
+    /// Letters are converted to kana
+    ///
+    /// default: true
+    pub enabled: bool,
+
+    /// Letters are converted to katakana instead of hiragana
+    ///
+    /// default: false
+    pub katakana: bool,
+
+    romaji: String,
+}
+
+impl RomajiInputMethod {
+    /// Creates a new input method that converts to hiragana
+    pub fn new() -> RomajiInputMethod {
+        RomajiInputMethod {
+            enabled: true,
+            katakana: false,
+            romaji: String::new(),
+        }
+    }
+
+    fn kana(&self, hiragana: &str) -> String {
+        if self.katakana { to_katakana(hiragana) } else { hiragana.to_string() }
+    }
+
+    /// Converts as much of the romaji as possible, returning the kana
+    fn convert(&mut self) -> String {
+        let mut kana = String::new();
+
+        while !self.romaji.is_empty() {
+            if let Some(&(_, hiragana)) = ROMAJI.iter().find(|&&(romaji, _)| romaji == self.romaji) {
+                kana.push_str(hiragana);
+                self.romaji.clear();
+                break;
+            }
+
+            let mut chars = self.romaji.chars();
+            let first = chars.next().unwrap_or_default();
+            let second = chars.next();
+
+            let consumed = match second {
+                Some('n') if first == 'n' => {
+                    kana.push('ん');
+                    2
+                }
+                Some(second) if first == 'n' && !is_vowel(second) && second != 'y' => {
+                    kana.push('ん');
+                    1
+                }
+                Some(second) if (first == second || (first == 't' && second == 'c')) && !is_vowel(first) => {
+                    kana.push('っ');
+                    1
+                }
+                // Letters that can't start any romaji are typed as they are
+                _ if !ROMAJI.iter().any(|&(romaji, _)| romaji.starts_with(self.romaji.as_str())) => {
+                    kana.push(first);
+                    1
+                }
+                _ => break,
+            };
+            self.romaji.drain(..consumed);
+        }
+
+        self.kana(&kana)
+    }
+
+    /// Converts the remaining romaji, with a trailing n typing ん
+    fn flush(&mut self) -> String {
+        let mut kana = self.convert();
+        if self.romaji == "n" {
+            kana.push_str(&self.kana("ん"));
+        } else {
+            kana.push_str(&self.romaji);
+        }
+        self.romaji.clear();
+        kana
+    }
+
+    fn commit(kana: String) -> InputMethodAction {
+        if kana.is_empty() { InputMethodAction::Preedit } else { InputMethodAction::Commit(kana) }
+    }
+}
+
+impl Default for RomajiInputMethod {
+    fn default() -> RomajiInputMethod {
+        RomajiInputMethod::new()
+    }
+}
+
+impl InputMethod for RomajiInputMethod {
+    fn key_press(&mut self, key: Key, logical: Option<&LogicalKeyboardEvent>) -> InputMethodAction {
+        match key {
+            keys::KEY_HENKAN => {
+                self.enabled = true;
+                return InputMethodAction::Preedit;
+            }
+            keys::KEY_MUHENKAN => {
+                self.enabled = false;
+                return RomajiInputMethod::commit(self.flush());
+            }
+            keys::KEY_KATAKANA_HIRAGANA => {
+                let kana = self.flush();
+                self.enabled = true;
+                self.katakana = !self.katakana;
+                return RomajiInputMethod::commit(kana);
+            }
+            _ => {}
+        }
+
+        if !self.enabled {
+            return InputMethodAction::Pass;
+        }
+
+        let symbol = match logical {
+            Some(&LogicalKeyboardEvent::Symbol(symbol)) => Some(symbol),
+            _ => None,
+        };
+
+        match symbol {
+            Some(letter) if letter.is_ascii_lowercase() => {
+                self.romaji.push(letter);
+                return RomajiInputMethod::commit(self.convert());
+            }
+            Some('\'') if self.romaji == "n" => {
+                self.romaji.clear();
+                return InputMethodAction::Commit(self.kana("ん"));
+            }
+            Some('-') => {
+                let mut kana = self.flush();
+                kana.push('ー');
+                return InputMethodAction::Commit(kana);
+            }
+            _ => {}
+        }
+
+        if self.romaji.is_empty() {
+            return InputMethodAction::Pass;
+        }
+
+        match key {
+            keys::KEY_SPACE | keys::KEY_ENTER | keys::KEY_NUM_ENTER => InputMethodAction::Commit(self.flush()),
+            keys::KEY_ESC => {
+                self.reset();
+                InputMethodAction::Preedit
+            }
+            keys::KEY_BACKSPACE => {
+                self.romaji.pop();
+                InputMethodAction::Preedit
+            }
+            _ => match symbol {
+                Some(symbol) => {
+                    let mut kana = self.flush();
+                    kana.push(symbol);
+                    InputMethodAction::Commit(kana)
+                }
+                None => InputMethodAction::Pass,
+            },
+        }
+    }
+
+    fn preedit(&self) -> Preedit {
+        Preedit {
+            text: self.romaji.clone(),
+            cursor: self.romaji.chars().count(),
+            candidates: Vec::new(),
+            selected: None,
+        }
+    }
+
+    fn reset(&mut self) {
+        self.romaji.clear();
+    }
+
+    fn clone_box(&self) -> Box<dyn InputMethod> {
+        Box::new(self.clone())
+    }
+}
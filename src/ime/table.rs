@@ -0,0 +1,149 @@
+use std::collections::HashMap;
+
+use event::LogicalKeyboardEvent;
+use ime::{InputMethod, InputMethodAction, Preedit};
+use keys::{self, Key};
+
+/// A simple input method that looks up the typed letters in a table of candidates
+///
+/// Lowercase letters are composed into the preedit. While composing:
+///  * Space commits the selected candidate (or the letters if there are none)
+///  * 1-9 commit that candidate, and Up/Down change the selected candidate
+///  * Enter commits the letters as they are typed, and Escape abandons them
+///  * Backspace, Left and Right edit the letters
+///  * Other symbols commit the selected candidate followed by the symbol
+///
+/// Other keys (such as commands) are passed on as usual.
+#[derive(Debug, Clone)]
+pub struct TableInputMethod {
+    pub table: HashMap<String, Vec<String>>,
+    text: String,
+    cursor: usize,
+    selected: usize,
+}
+
+impl TableInputMethod {
+    /// Creates a new input method with the specified table
+    pub fn new(table: HashMap<String, Vec<String>>) -> TableInputMethod {
+        TableInputMethod {
+            table,
+            text: String::new(),
+            cursor: 0,
+            selected: 0,
+        }
+    }
+
+    /// Creates an input method with a few pinyin syllables, for demonstrations and testing
+    pub fn pinyin_demo() -> TableInputMethod {
+        let table = hashmap!{
+            "ni" => vec!["你", "尼", "泥"],
+            "hao" => vec!["好", "号", "毫"],
+            "nihao" => vec!["你好"],
+            "zhong" => vec!["中", "种", "重"],
+            "wen" => vec!["文", "问", "闻"],
+            "zhongwen" => vec!["中文"],
+            "xie" => vec!["谢", "写", "些"],
+            "xiexie" => vec!["谢谢"],
+        };
+
+        TableInputMethod::new(table.into_iter()
+            .map(|(syllables, candidates)| (syllables.to_string(), candidates.into_iter().map(|c| c.to_string()).collect()))
+            .collect())
+    }
+
+    fn candidates(&self) -> &[String] {
+        self.table.get(&self.text).map_or(&[], |candidates| candidates.as_slice())
+    }
+
+    /// Commits the candidate (or the letters if there isn't one) and clears the preedit
+    fn commit(&mut self, candidate: Option<usize>) -> String {
+        let text = match candidate.and_then(|index| self.candidates().get(index)) {
+            Some(candidate) => candidate.clone(),
+            None => self.text.clone(),
+        };
+        self.reset();
+        text
+    }
+
+    /// Returns the byte offset of the cursor in text
+    fn cursor_offset(&self) -> usize {
+        self.text.char_indices().nth(self.cursor).map_or(self.text.len(), |(offset, _)| offset)
+    }
+}
+
+impl InputMethod for TableInputMethod {
+    fn key_press(&mut self, key: Key, logical: Option<&LogicalKeyboardEvent>) -> InputMethodAction {
+        let symbol = match logical {
+            Some(&LogicalKeyboardEvent::Symbol(symbol)) => Some(symbol),
+            _ => None,
+        };
+
+        if let Some(letter) = symbol.filter(|c| c.is_ascii_lowercase()) {
+            let offset = self.cursor_offset();
+            self.text.insert(offset, letter);
+            self.cursor += 1;
+            self.selected = 0;
+            return InputMethodAction::Preedit;
+        }
+
+        if self.text.is_empty() {
+            return InputMethodAction::Pass;
+        }
+
+        match key {
+            keys::KEY_SPACE => return InputMethodAction::Commit(self.commit(Some(self.selected))),
+            keys::KEY_ENTER | keys::KEY_NUM_ENTER => return InputMethodAction::Commit(self.commit(None)),
+            keys::KEY_ESC => self.reset(),
+            keys::KEY_BACKSPACE => {
+                if self.cursor > 0 {
+                    self.cursor -= 1;
+                    let offset = self.cursor_offset();
+                    self.text.remove(offset);
+                    self.selected = 0;
+                }
+            }
+            keys::KEY_LEFT => self.cursor = self.cursor.saturating_sub(1),
+            keys::KEY_RIGHT => self.cursor = (self.cursor + 1).min(self.text.chars().count()),
+            keys::KEY_UP => self.selected = self.selected.saturating_sub(1),
+            keys::KEY_DOWN => self.selected = (self.selected + 1).min(self.candidates().len().saturating_sub(1)),
+            _ => match symbol {
+                Some(digit @ '1' ..= '9') => {
+                    let index = digit as usize - '1' as usize;
+                    if index < self.candidates().len() {
+                        return InputMethodAction::Commit(self.commit(Some(index)));
+                    }
+                }
+                Some(symbol) => {
+                    let mut text = self.commit(Some(self.selected));
+                    text.push(symbol);
+                    return InputMethodAction::Commit(text);
+                }
+                None => return InputMethodAction::Pass,
+            },
+        }
+
+        InputMethodAction::Preedit
+    }
+
+    fn preedit(&self) -> Preedit {
+        let candidates = self.candidates().to_vec();
+        let selected = if candidates.is_empty() { None } else { Some(self.selected) };
+
+        Preedit {
+            text: self.text.clone(),
+            cursor: self.cursor,
+            candidates,
+            selected,
+        }
+    }
+
+    fn reset(&mut self) {
+        self.text.clear();
+        self.cursor = 0;
+        self.selected = 0;
+    }
+
+    fn clone_box(&self) -> Box<dyn InputMethod> {
+        Box::new(self.clone())
+    }
+}
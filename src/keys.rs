@@ -15,6 +15,8 @@ pub enum KeyType {
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub struct Key(u8);
 
+/// Used when the physical key isn't known, eg. for symbols typed in a terminal that no key types
+pub const KEY_UNKNOWN: Key = Key(0);
 pub const KEY_ESC: Key = Key(1);
 pub const KEY_1: Key = Key(2);
 pub const KEY_2: Key = Key(3);
@@ -98,6 +100,12 @@ pub const KEY_NUM_2: Key = Key(80);
 pub const KEY_NUM_3: Key = Key(81);
 pub const KEY_NUM_0: Key = Key(82);
 pub const KEY_NUM_DECIMAL: Key = Key(83);
+pub const KEY_SYS_RQ: Key = Key(84);
+pub const KEY_B_SLASH: Key = Key(86);
+pub const KEY_RO: Key = Key(89);
+pub const KEY_HENKAN: Key = Key(92);
+pub const KEY_KATAKANA_HIRAGANA: Key = Key(93);
+pub const KEY_MUHENKAN: Key = Key(94);
 pub const KEY_F11: Key = Key(87);
 pub const KEY_F12: Key = Key(88);
 pub const KEY_NUM_ENTER: Key = Key(96);
@@ -116,6 +124,7 @@ pub const KEY_PG_DOWN: Key = Key(109);
 pub const KEY_INS: Key = Key(110);
 pub const KEY_DEL: Key = Key(111);
 pub const KEY_PAUSE: Key = Key(119);
+pub const KEY_YEN: Key = Key(124);
 pub const KEY_L_SUPER: Key = Key(125);
 pub const KEY_R_SUPER: Key = Key(126);
 pub const KEY_MENU: Key = Key(127);
@@ -185,6 +194,7 @@ impl Key {
             KEY_NUM_SUB => '-',
             KEY_NUM_ADD => '+',
             KEY_NUM_DECIMAL => '.',
+            KEY_B_SLASH => '\\',
             KEY_NUM_DIV => '/',
             _ => '\0'
         }
@@ -278,6 +288,12 @@ impl Key {
             KEY_NUM_3 => "NUM 3",
             KEY_NUM_0 => "NUM 0",
             KEY_NUM_DECIMAL => "NUM .",
+            KEY_SYS_RQ => "SYS RQ",
+            KEY_B_SLASH => "\\",
+            KEY_RO => "RO",
+            KEY_HENKAN => "HENKAN",
+            KEY_KATAKANA_HIRAGANA => "KATAKANA/HIRAGANA",
+            KEY_MUHENKAN => "MUHENKAN",
             KEY_F11 => "F11",
             KEY_F12 => "F12",
             KEY_NUM_ENTER => "NUM ENTER",
@@ -296,6 +312,7 @@ impl Key {
             KEY_INS => "INSERT",
             KEY_DEL => "DELETE",
             KEY_PAUSE => "PAUSE",
+            KEY_YEN => "YEN",
             KEY_L_SUPER => "L SUPER",
             KEY_R_SUPER => "R SUPER",
             KEY_MENU => "MENU",
@@ -303,6 +320,18 @@ impl Key {
         }
     }
 
+    /// Finds a key by its name (see `name`)
+    ///
+    /// Case, spaces and underscores are ignored, so "Caps Lock", "CAPS_LOCK" and "capslock" all match.
+    pub fn from_name(name: &str) -> Option<Key> {
+        let normalise = |name: &str| -> String {
+            name.chars().filter(|&c| c != ' ' && c != '_').flat_map(|c| c.to_uppercase()).collect()
+        };
+        let name = normalise(name);
+
+        (0..=255).map(Key).find(|key| key.name() != "UNNAMED" && normalise(key.name()) == name)
+    }
+
     /// Returns true if the key represents an alphabetic character
     pub fn is_alphabetic(&self) -> bool {
         self.key_type() == Some(KeyType::Alphabetic)
@@ -334,14 +363,28 @@ impl Key {
         self.key_type() == Some(KeyType::Numpad)
     }
 
+    /// Returns true if the key is a modifier key
+    ///
+    /// These are the shift, ctrl, alt, alt gr and super keys.
+    pub fn is_modifier(&self) -> bool {
+        matches!(*self, KEY_L_SHIFT | KEY_R_SHIFT | KEY_L_CTRL | KEY_R_CTRL | KEY_ALT | KEY_ALT_GR | KEY_L_SUPER | KEY_R_SUPER)
+    }
+
+    /// Returns true if the key is a lock key
+    ///
+    /// These are the caps lock, num lock and scroll lock keys.
+    pub fn is_lock(&self) -> bool {
+        matches!(*self, KEY_CAPS_LOCK | KEY_NUM_LOCK | KEY_SCROLL_LOCK)
+    }
+
     /// Returns the type of the key which used to determine which modifers can alter the keys level
     pub fn key_type(&self) -> Option<KeyType> {
         match self.keycode() {
-            16 ... 25 | 30 ... 38 | 44 ... 50 => Some(KeyType::Alphabetic),
-            2 ... 11 => Some(KeyType::Numeric),
-            12 | 13 | 26 | 27 | 39 | 40 | 41 | 43 | 51 | 52 | 53 | 55 | 98 => Some(KeyType::Punctuation),
-            1 | 14 | 15 | 28 | 29 | 42 | 54 | 56 ... 70 | 74 | 78 | 87 | 88 | 96 | 97 | 99 | 100 | 102 ... 111 | 119 | 125 | 126 | 127  => Some(KeyType::Control),
-            71 | 72 | 73 | 75 | 76 | 77 | 79 ... 83 => Some(KeyType::Numpad),
+            16 ..= 25 | 30 ..= 38 | 44 ..= 50 => Some(KeyType::Alphabetic),
+            2 ..= 11 => Some(KeyType::Numeric),
+            12 | 13 | 26 | 27 | 39 | 40 | 41 | 43 | 51 | 52 | 53 | 55 | 86 | 89 | 98 | 124 => Some(KeyType::Punctuation),
+            1 | 14 | 15 | 28 | 29 | 42 | 54 | 56 ..= 70 | 74 | 78 | 84 | 87 | 88 | 92 ..= 94 | 96 | 97 | 99 | 100 | 102 ..= 111 | 119 | 125 | 126 | 127  => Some(KeyType::Control),
+            71 | 72 | 73 | 75 | 76 | 77 | 79 ..= 83 => Some(KeyType::Numpad),
             _ => None,
         }
     }
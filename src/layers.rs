@@ -0,0 +1,161 @@
+use std::collections::HashMap;
+
+use keys::Key;
+
+/// What a key does on a layer
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LayerAction {
+    /// Acts as the specified key
+    Key(Key),
+
+    /// Does nothing, blocking the keys on lower layers
+    None,
+
+    /// Activates the layer while the key is held
+    Momentary(usize),
+
+    /// Activates or deactivates the layer each time the key is pressed
+    Toggle(usize),
+
+    /// Activates the layer until the next key is pressed
+    OneShot(usize),
+}
+
+/// A set of keys that act differently while the layer is active
+///
+/// Keys that are not in key_map are transparent, so they do whatever they do on the layers below.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Layer {
+    pub name: String,
+    pub key_map: HashMap<Key, LayerAction>,
+}
+
+impl Layer {
+    /// Creates a new layer where every key is transparent
+    pub fn new(name: &str) -> Layer {
+        Layer {
+            name: name.to_string(),
+            key_map: HashMap::new(),
+        }
+    }
+}
+
+/// A stack of layers, used to reach keys that a keyboard doesn't have (eg. with an Fn key)
+///
+/// Every key press and release should be passed to `feed`, and the events it returns passed on
+/// to KeyboardState. Each key is looked up on the active layers from the top down, and keys that
+/// aren't mapped on any active layer act as themselves. Layer 0 is always active.
+///
+/// The action of a key is decided when it is pressed, so its release still releases the same key
+/// if the layers change while it is held.
+#[derive(Debug, Clone, Default)]
+pub struct LayerStack {
+    pub layers: Vec<Layer>,
+
+    /// The number of keys holding each layer active
+    momentary: Vec<u8>,
+
+    /// Whether each layer has been toggled on
+    toggled: Vec<bool>,
+
+    /// The layer that is active until the next key is pressed
+    one_shot: Option<usize>,
+
+    /// The actions of the keys that are held, by keycode
+    pressed: HashMap<u8, LayerAction>,
+}
+
+impl LayerStack {
+    /// Creates a new layer stack with an empty base layer
+    pub fn new() -> LayerStack {
+        let mut stack = LayerStack::default();
+        stack.add_layer(Layer::new("base"));
+        stack
+    }
+
+    /// Adds a layer to the top of the stack and returns its index
+    pub fn add_layer(&mut self, layer: Layer) -> usize {
+        self.layers.push(layer);
+        self.momentary.push(0);
+        self.toggled.push(false);
+        self.layers.len() - 1
+    }
+
+    /// Returns true if the layer is active
+    pub fn is_active(&self, layer: usize) -> bool {
+        layer == 0
+            || matches!(self.momentary.get(layer), Some(&count) if count > 0)
+            || self.toggled.get(layer).cloned().unwrap_or(false)
+            || self.one_shot == Some(layer)
+    }
+
+    /// Returns the indexes of the active layers, from the bottom up
+    pub fn active_layers(&self) -> Vec<usize> {
+        (0..self.layers.len()).filter(|&layer| self.is_active(layer)).collect()
+    }
+
+    /// Returns what the key would do if it was pressed now
+    pub fn action(&self, key: Key) -> LayerAction {
+        self.active_layers().into_iter().rev()
+            .filter_map(|layer| self.layers[layer].key_map.get(&key).cloned())
+            .next()
+            .unwrap_or(LayerAction::Key(key))
+    }
+
+    /// Should be called whenever a key is pressed or released
+    ///
+    /// Returns the key events that should be passed to KeyboardState.
+    pub fn feed(&mut self, key: Key, pressed: bool) -> Vec<(Key, bool)> {
+        if !pressed {
+            return match self.pressed.remove(&key.keycode()) {
+                Some(LayerAction::Key(target)) => vec![(target, false)],
+                Some(LayerAction::Momentary(layer)) => {
+                    if let Some(count) = self.momentary.get_mut(layer) {
+                        *count = count.saturating_sub(1);
+                    }
+                    Vec::new()
+                }
+                Some(_) => Vec::new(),
+                None => vec![(key, false)],
+            };
+        }
+
+        // Hardware repeats keep the action they were pressed with
+        if let Some(&action) = self.pressed.get(&key.keycode()) {
+            return match action {
+                LayerAction::Key(target) => vec![(target, true)],
+                _ => Vec::new(),
+            };
+        }
+
+        let action = self.action(key);
+        self.pressed.insert(key.keycode(), action);
+
+        match action {
+            LayerAction::Key(target) => {
+                self.one_shot = None;
+                vec![(target, true)]
+            }
+            LayerAction::None => {
+                self.one_shot = None;
+                Vec::new()
+            }
+            LayerAction::Momentary(layer) => {
+                if let Some(count) = self.momentary.get_mut(layer) {
+                    *count += 1;
+                }
+                Vec::new()
+            }
+            LayerAction::Toggle(layer) => {
+                if let Some(toggled) = self.toggled.get_mut(layer) {
+                    *toggled = !*toggled;
+                }
+                Vec::new()
+            }
+            LayerAction::OneShot(layer) => {
+                self.one_shot = Some(layer);
+                Vec::new()
+            }
+        }
+    }
+}
@@ -0,0 +1,172 @@
+use std::collections::BTreeSet;
+use std::fmt;
+
+use keys::Key;
+use layout::{CapsBehaviour, Layout, LayoutInfo};
+
+/// Describes how a single value differs between two layouts
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Change<T> {
+    Added(T),
+    Removed(T),
+    Changed(T, T),
+}
+
+/// A symbol that differs between two layouts
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SymbolChange {
+    pub group: u8,
+    pub level: u8,
+    pub key: Key,
+    pub change: Change<String>,
+}
+
+/// A key that is affected by the caps lock differently in two layouts
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CapsChange {
+    pub key: Key,
+    pub old: CapsBehaviour,
+    pub new: CapsBehaviour,
+}
+
+/// A field of `LayoutInfo` that differs between two layouts
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InfoChange {
+    /// The name of the field (eg. "locale")
+    pub field: &'static str,
+    pub change: Change<String>,
+}
+
+/// The differences between two layouts
+///
+/// Symbols from key_symbol_map and key_string_map are compared together, so moving a
+/// symbol from one map to the other is not reported.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct LayoutDiff {
+    /// Details of the layouts that differ. Only set by `diff_with_info`
+    pub info: Vec<InfoChange>,
+
+    /// Set if has_alt_gr_key differs, containing the old and new values
+    pub has_alt_gr_key: Option<(bool, bool)>,
+
+    /// Keys whose caps lock behaviour differs
+    pub caps_behaviours: Vec<CapsChange>,
+
+    /// Symbols that were added, removed or changed, ordered by key, group and level
+    pub symbols: Vec<SymbolChange>,
+}
+
+impl LayoutDiff {
+    /// Returns true if the layouts are the same
+    pub fn is_empty(&self) -> bool {
+        self.info.is_empty() && self.has_alt_gr_key.is_none() && self.caps_behaviours.is_empty() && self.symbols.is_empty()
+    }
+}
+
+fn get_symbol(layout: &Layout, (group, level, key): (u8, u8, Key)) -> Option<String> {
+    match layout.get_symbol(group, level, key) {
+        Some(symbol) => Some(symbol.to_string()),
+        None => layout.get_string(group, level, key).map(|string| string.to_string()),
+    }
+}
+
+/// Compares two layouts
+pub fn diff(old: &Layout, new: &Layout) -> LayoutDiff {
+    let mut diff = LayoutDiff::default();
+
+    if old.has_alt_gr_key != new.has_alt_gr_key {
+        diff.has_alt_gr_key = Some((old.has_alt_gr_key, new.has_alt_gr_key));
+    }
+
+    let caps_keys: BTreeSet<u8> = old.key_caps_map.keys().chain(new.key_caps_map.keys())
+        .map(|key| key.keycode())
+        .collect();
+    for keycode in caps_keys {
+        let key = Key::from_keycode(keycode);
+        let (old, new) = (old.caps_behaviour(key), new.caps_behaviour(key));
+        if old != new {
+            diff.caps_behaviours.push(CapsChange { key, old, new });
+        }
+    }
+
+    // Keys don't implement Ord, so sort by keycode instead
+    let positions: BTreeSet<(u8, u8, u8)> = old.key_symbol_map.keys()
+        .chain(old.key_string_map.keys())
+        .chain(new.key_symbol_map.keys())
+        .chain(new.key_string_map.keys())
+        .map(|&(group, level, key)| (key.keycode(), group, level))
+        .collect();
+    for (keycode, group, level) in positions {
+        let key = Key::from_keycode(keycode);
+        let change = match (get_symbol(old, (group, level, key)), get_symbol(new, (group, level, key))) {
+            (Some(old), Some(new)) => if old != new { Change::Changed(old, new) } else { continue },
+            (Some(old), None) => Change::Removed(old),
+            (None, Some(new)) => Change::Added(new),
+            (None, None) => continue,
+        };
+
+        diff.symbols.push(SymbolChange { group, level, key, change });
+    }
+
+    diff
+}
+
+/// Compares two layouts, including their details (name, locale, etc)
+pub fn diff_with_info(old: (&LayoutInfo, &Layout), new: (&LayoutInfo, &Layout)) -> LayoutDiff {
+    let mut diff = diff(old.1, new.1);
+    let (old, new) = (old.0, new.0);
+
+    let fields = [
+        ("id", Some(&old.id), Some(&new.id)),
+        ("variant", old.variant.as_ref(), new.variant.as_ref()),
+        ("name", Some(&old.name), Some(&new.name)),
+        ("locale", Some(&old.locale), Some(&new.locale)),
+        ("script", Some(&old.script), Some(&new.script)),
+    ];
+    for &(field, old, new) in &fields {
+        let change = match (old, new) {
+            (Some(old), Some(new)) => if old != new { Change::Changed(old.clone(), new.clone()) } else { continue },
+            (Some(old), None) => Change::Removed(old.clone()),
+            (None, Some(new)) => Change::Added(new.clone()),
+            (None, None) => continue,
+        };
+
+        diff.info.push(InfoChange { field, change });
+    }
+
+    diff
+}
+
+impl fmt::Display for LayoutDiff {
+    /// Formats the differences for code review, one per line
+    ///
+    /// Added entries start with '+', removed entries with '-' and changed entries with '~'.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for change in &self.info {
+            match change.change {
+                Change::Added(ref new) => writeln!(f, "+ {}: {:?}", change.field, new)?,
+                Change::Removed(ref old) => writeln!(f, "- {}: {:?}", change.field, old)?,
+                Change::Changed(ref old, ref new) => writeln!(f, "~ {}: {:?} -> {:?}", change.field, old, new)?,
+            }
+        }
+
+        if let Some((old, new)) = self.has_alt_gr_key {
+            writeln!(f, "~ has_alt_gr_key: {} -> {}", old, new)?;
+        }
+
+        for change in &self.caps_behaviours {
+            writeln!(f, "~ caps lock on {}: {:?} -> {:?}", change.key.name(), change.old, change.new)?;
+        }
+
+        for change in &self.symbols {
+            let position = format!("{} (group {}, level {})", change.key.name(), change.group, change.level);
+            match change.change {
+                Change::Added(ref new) => writeln!(f, "+ {}: {:?}", position, new)?,
+                Change::Removed(ref old) => writeln!(f, "- {}: {:?}", position, old)?,
+                Change::Changed(ref old, ref new) => writeln!(f, "~ {}: {:?} -> {:?}", position, old, new)?,
+            }
+        }
+
+        Ok(())
+    }
+}
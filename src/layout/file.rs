@@ -0,0 +1,187 @@
+use std::collections::{BTreeMap, HashMap};
+use std::error::Error;
+use std::fmt;
+
+use keys::Key;
+use layout::{CapsBehaviour, Layout, LayoutInfo};
+
+/// An error from parsing a layout file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseLayoutError {
+    /// The line the error is on, starting from 1
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for ParseLayoutError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl Error for ParseLayoutError {}
+
+/// Removes the first word from the text and returns it
+fn next_word<'a>(text: &mut &'a str) -> &'a str {
+    let trimmed = text.trim_start();
+    let end = trimmed.find(char::is_whitespace).unwrap_or(trimmed.len());
+    *text = &trimmed[end..];
+    &trimmed[..end]
+}
+
+/// Returns the name of the key, with underscores instead of spaces so it is a single word
+fn key_name(key: Key) -> String {
+    key.name().replace(' ', "_")
+}
+
+/// Parses a quoted string, as written by `{:?}`
+fn parse_quoted(text: &str) -> Result<String, String> {
+    if text.len() < 2 || !text.starts_with('"') || !text.ends_with('"') {
+        return Err(format!("expected a quoted string, found '{}'", text));
+    }
+    let inner = &text[1..text.len() - 1];
+
+    let mut string = String::new();
+    let mut chars = inner.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            string.push(c);
+            continue;
+        }
+
+        let escaped = match chars.next() {
+            Some('n') => '\n',
+            Some('t') => '\t',
+            Some('r') => '\r',
+            Some('0') => '\0',
+            Some(c @ '\\') | Some(c @ '"') | Some(c @ '\'') => c,
+            Some('u') => {
+                let code: String = chars.by_ref().skip(1).take_while(|&c| c != '}').collect();
+                u32::from_str_radix(&code, 16).ok()
+                    .and_then(::std::char::from_u32)
+                    .ok_or_else(|| format!("invalid escape '\\u{{{}}}'", code))?
+            }
+            _ => return Err(format!("invalid escape in '{}'", text)),
+        };
+        string.push(escaped);
+    }
+
+    Ok(string)
+}
+
+fn parse_caps_behaviour(text: &str) -> Result<CapsBehaviour, String> {
+    match text {
+        "Alphabetic" => Ok(CapsBehaviour::Alphabetic),
+        "Unaffected" => Ok(CapsBehaviour::Unaffected),
+        "ShiftLock" => Ok(CapsBehaviour::ShiftLock),
+        _ => Err(format!("unknown caps lock behaviour '{}'", text)),
+    }
+}
+
+/// Parses a layout file, returning the layout and its details
+///
+/// Each line of the file is a setting, a caps lock behaviour or a symbol. Blank lines and
+/// lines starting with '#' are ignored:
+///
+/// ```text
+/// id gb
+/// name English (UK)
+/// locale en-GB
+/// script Latn
+/// alt_gr true
+/// caps ' Alphabetic
+/// symbol 3 0 1 "£"
+/// ```
+///
+/// Symbols are given as the key name (with underscores instead of spaces), group, level and
+/// a quoted string. `to_text` writes layouts in this format.
+pub fn parse(text: &str) -> Result<(LayoutInfo, Layout), ParseLayoutError> {
+    let mut info = LayoutInfo::new("", "", "", "");
+    let mut layout = Layout {
+        has_alt_gr_key: false,
+        key_symbol_map: HashMap::new(),
+        key_string_map: HashMap::new(),
+        key_caps_map: HashMap::new(),
+    };
+
+    for (index, line) in text.lines().enumerate() {
+        let error = |message: String| ParseLayoutError { line: index + 1, message };
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let mut value = line;
+        let setting = next_word(&mut value);
+        let value = value.trim();
+        let parse_key = |name: &str| Key::from_name(name).ok_or_else(|| error(format!("unknown key '{}'", name)));
+
+        match setting {
+            "id" => info.id = value.to_string(),
+            "variant" => info.variant = Some(value.to_string()),
+            "name" => info.name = value.to_string(),
+            "locale" => info.locale = value.to_string(),
+            "script" => info.script = value.to_string(),
+            "alt_gr" => layout.has_alt_gr_key = value.parse().map_err(|_| error(format!("expected true or false, found '{}'", value)))?,
+            "caps" => {
+                let mut rest = value;
+                let key = parse_key(next_word(&mut rest))?;
+                let behaviour = parse_caps_behaviour(rest.trim()).map_err(&error)?;
+                layout.key_caps_map.insert(key, behaviour);
+            }
+            "symbol" => {
+                let mut rest = value;
+                let key = parse_key(next_word(&mut rest))?;
+                let mut number = || {
+                    let word = next_word(&mut rest);
+                    word.parse::<u8>().map_err(|_| error(format!("expected a group and level, found '{}'", word)))
+                };
+                let (group, level) = (number()?, number()?);
+                let symbol = parse_quoted(rest.trim()).map_err(&error)?;
+
+                let mut chars = symbol.chars();
+                match (chars.next(), chars.next()) {
+                    (Some(c), None) => { layout.key_symbol_map.insert((group, level, key), c); }
+                    (Some(_), Some(_)) => { layout.key_string_map.insert((group, level, key), symbol); }
+                    (None, _) => return Err(error("symbols can't be empty".to_string())),
+                }
+            }
+            _ => return Err(error(format!("unknown setting '{}'", setting))),
+        }
+    }
+
+    Ok((info, layout))
+}
+
+/// Writes a layout in the format read by `parse`
+///
+/// Symbols are written in key, group and level order, so the output of two versions of a
+/// layout can also be compared with a text diff.
+pub fn to_text(info: &LayoutInfo, layout: &Layout) -> String {
+    let mut text = String::new();
+
+    text.push_str(&format!("id {}\n", info.id));
+    if let Some(ref variant) = info.variant {
+        text.push_str(&format!("variant {}\n", variant));
+    }
+    text.push_str(&format!("name {}\nlocale {}\nscript {}\n", info.name, info.locale, info.script));
+    text.push_str(&format!("alt_gr {}\n", layout.has_alt_gr_key));
+
+    let caps: BTreeMap<u8, CapsBehaviour> = layout.key_caps_map.iter()
+        .map(|(key, behaviour)| (key.keycode(), *behaviour))
+        .collect();
+    for (keycode, behaviour) in caps {
+        text.push_str(&format!("caps {} {:?}\n", key_name(Key::from_keycode(keycode)), behaviour));
+    }
+
+    // key_symbol_map comes last so its symbols replace any for the same key in key_string_map
+    let symbols: BTreeMap<(u8, u8, u8), String> = layout.key_string_map.iter()
+        .map(|(&(group, level, key), string)| ((key.keycode(), group, level), string.clone()))
+        .chain(layout.key_symbol_map.iter().map(|(&(group, level, key), symbol)| ((key.keycode(), group, level), symbol.to_string())))
+        .collect();
+    for ((keycode, group, level), symbol) in symbols {
+        text.push_str(&format!("symbol {} {} {} {:?}\n", key_name(Key::from_keycode(keycode)), group, level, symbol));
+    }
+
+    text
+}
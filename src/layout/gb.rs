@@ -1,3 +1,5 @@
+use std::collections::HashMap;
+
 use keys;
 use layout::Layout;
 
@@ -80,6 +82,8 @@ pub fn gb() -> Layout {
             (1, 0, keys::KEY_BACKTICK) => '|',
             (0, 0, keys::KEY_HASH) => '#',
             (0, 1, keys::KEY_HASH) => '~',
+            (0, 0, keys::KEY_B_SLASH) => '\\',
+            (0, 1, keys::KEY_B_SLASH) => '|',
             (0, 0, keys::KEY_Z) => 'z',
             (0, 1, keys::KEY_Z) => 'Z',
             (0, 0, keys::KEY_X) => 'x',
@@ -121,6 +125,8 @@ pub fn gb() -> Layout {
             (0, 1, keys::KEY_NUM_DECIMAL) => '.',
             (0, 0, keys::KEY_NUM_DIV) => '/',
             (0, 1, keys::KEY_NUM_DIV) => '/',
-        }
+        },
+        key_string_map: HashMap::new(),
+        key_caps_map: HashMap::new(),
     }
 }
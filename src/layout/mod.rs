@@ -1,21 +1,63 @@
 use std::collections::HashMap;
 
-use keys::Key;
+use keys::{Key, KeyType};
 
-#[derive(Debug, Clone)]
+/// Describes how the caps lock affects a key
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CapsBehaviour {
+    /// Caps lock selects level 1, the shift key negates it (default for alphabetic keys)
+    Alphabetic,
+    /// Caps lock has no effect on the key (default for all other keys)
+    Unaffected,
+    /// Caps lock selects level 1, just like holding the shift key
+    ShiftLock,
+}
+
+#[derive(Debug, Clone, PartialEq)]
 pub struct Layout {
     pub has_alt_gr_key: bool,
     pub key_symbol_map: HashMap<(u8, u8, Key), char>,
+
+    /// Symbols that are made of more than one character, such as ligatures or grapheme clusters
+    ///
+    /// This is only checked when the key has no entry in key_symbol_map.
+    pub key_string_map: HashMap<(u8, u8, Key), String>,
+
+    /// Overrides how the caps lock affects specific keys
+    ///
+    /// Keys that are not in this map use the default for their key type.
+    /// For example, the German layout would mark the 'ü', 'ö' and 'ä' keys as `Alphabetic`.
+    pub key_caps_map: HashMap<Key, CapsBehaviour>,
 }
 
 impl Layout {
     pub fn get_symbol(&self, group: u8, level: u8, key: Key) -> Option<char> {
         self.key_symbol_map.get(&(group, level, key)).cloned()
     }
+
+    /// Returns the multi-character symbol for the specified key, if it has one
+    pub fn get_string(&self, group: u8, level: u8, key: Key) -> Option<&str> {
+        self.key_string_map.get(&(group, level, key)).map(|string| string.as_str())
+    }
+
+    /// Returns how the caps lock affects the specified key
+    pub fn caps_behaviour(&self, key: Key) -> CapsBehaviour {
+        match self.key_caps_map.get(&key) {
+            Some(behaviour) => *behaviour,
+            None if key.key_type() == Some(KeyType::Alphabetic) => CapsBehaviour::Alphabetic,
+            None => CapsBehaviour::Unaffected,
+        }
+    }
 }
 
 mod us;
 mod gb;
+mod registry;
+mod diff;
+mod file;
 
 pub use self::us::us;
 pub use self::gb::gb;
+pub use self::registry::{LayoutInfo, LayoutRegistry};
+pub use self::diff::{diff, diff_with_info, Change, CapsChange, InfoChange, LayoutDiff, SymbolChange};
+pub use self::file::{parse, to_text, ParseLayoutError};
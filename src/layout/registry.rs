@@ -0,0 +1,183 @@
+use layout::{self, Layout};
+
+/// Describes a keyboard layout that is available in a `LayoutRegistry`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LayoutInfo {
+    /// Short identifier of the layout, following XKB naming (eg. "gb", "de")
+    pub id: String,
+
+    /// The variant of the layout (eg. "nodeadkeys"). None for the default variant
+    pub variant: Option<String>,
+
+    /// Human readable name (eg. "English (UK)")
+    pub name: String,
+
+    /// BCP-47 tag of the locale the layout is intended for (eg. "en-GB")
+    pub locale: String,
+
+    /// ISO 15924 code of the script the layout types (eg. "Latn")
+    pub script: String,
+}
+
+impl LayoutInfo {
+    pub fn new(id: &str, name: &str, locale: &str, script: &str) -> LayoutInfo {
+        LayoutInfo {
+            id: id.to_string(),
+            variant: None,
+            name: name.to_string(),
+            locale: locale.to_string(),
+            script: script.to_string(),
+        }
+    }
+
+    /// Returns a copy of this info for the specified variant
+    pub fn with_variant(mut self, variant: &str) -> LayoutInfo {
+        self.variant = Some(variant.to_string());
+        self
+    }
+
+    /// Returns the full name of the layout, including the variant (eg. "de(nodeadkeys)")
+    pub fn full_id(&self) -> String {
+        match self.variant {
+            Some(ref variant) => format!("{}({})", self.id, variant),
+            None => self.id.clone(),
+        }
+    }
+
+    fn matches(&self, id: &str, variant: Option<&str>) -> bool {
+        self.id == id && self.variant.as_deref() == variant
+    }
+}
+
+/// Splits a layout name such as "de(nodeadkeys)" into its id and variant
+fn parse_name(name: &str) -> (&str, Option<&str>) {
+    let name = name.trim();
+
+    if name.ends_with(')') {
+        if let Some(open) = name.find('(') {
+            return (&name[..open], Some(&name[open + 1..name.len() - 1]));
+        }
+    }
+
+    (name, None)
+}
+
+/// Normalises a locale tag so "en_gb" and "en-GB" compare equal
+fn normalise_locale(locale: &str) -> String {
+    locale.trim().replace('_', "-").to_lowercase()
+}
+
+/// A list of the keyboard layouts that are available to the system
+///
+/// Layouts can be looked up by name ("gb", "de(nodeadkeys)") or by locale ("en-GB").
+#[derive(Debug, Clone, Default)]
+pub struct LayoutRegistry {
+    entries: Vec<(LayoutInfo, Layout)>,
+    fallback: Option<usize>,
+}
+
+impl LayoutRegistry {
+    /// Creates an empty registry
+    pub fn new() -> LayoutRegistry {
+        LayoutRegistry::default()
+    }
+
+    /// Creates a registry containing all the layouts built into orbkb
+    ///
+    /// The US layout is used as the fallback.
+    pub fn builtin() -> LayoutRegistry {
+        let mut registry = LayoutRegistry::new();
+        registry.register(LayoutInfo::new("us", "English (US)", "en-US", "Latn"), layout::us());
+        registry.register(LayoutInfo::new("gb", "English (UK)", "en-GB", "Latn"), layout::gb());
+        registry
+    }
+
+    /// Adds a layout to the registry
+    ///
+    /// If a layout with the same id and variant is already registered, it will be replaced.
+    pub fn register(&mut self, info: LayoutInfo, layout: Layout) {
+        let existing = self.entries.iter().position(|(existing, _)| {
+            existing.matches(&info.id, info.variant.as_deref())
+        });
+
+        match existing {
+            Some(index) => self.entries[index] = (info, layout),
+            None => self.entries.push((info, layout)),
+        }
+    }
+
+    /// Sets the layout that is returned by `resolve` when nothing else matches
+    ///
+    /// Returns false if no layout with that name has been registered.
+    pub fn set_fallback(&mut self, name: &str) -> bool {
+        let (id, variant) = parse_name(name);
+
+        match self.entries.iter().position(|(info, _)| info.matches(id, variant)) {
+            Some(index) => {
+                self.fallback = Some(index);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Returns the details of all registered layouts, in the order they were registered
+    pub fn layouts(&self) -> Vec<&LayoutInfo> {
+        self.entries.iter().map(|(info, _)| info).collect()
+    }
+
+    /// Finds a layout by its id and variant
+    pub fn get(&self, id: &str, variant: Option<&str>) -> Option<(&LayoutInfo, &Layout)> {
+        self.entries.iter()
+            .find(|(info, _)| info.matches(id, variant))
+            .map(|(info, layout)| (info, layout))
+    }
+
+    /// Finds a layout by name ("gb", "de(nodeadkeys)") or locale ("en-GB")
+    ///
+    /// Only exact matches are returned. See `resolve` for a lookup that falls back to similar layouts.
+    pub fn lookup(&self, name: &str) -> Option<(&LayoutInfo, &Layout)> {
+        let (id, variant) = parse_name(name);
+
+        if let Some(found) = self.get(id, variant) {
+            return Some(found);
+        }
+
+        let locale = normalise_locale(name);
+        self.entries.iter()
+            .find(|(info, _)| info.variant.is_none() && normalise_locale(&info.locale) == locale)
+            .map(|(info, layout)| (info, layout))
+    }
+
+    /// Finds the layout that best matches the specified name or locale
+    ///
+    /// This tries the following in order:
+    ///  * An exact match (see `lookup`)
+    ///  * The default variant of the requested layout (eg. "de" when "de(neo)" is missing)
+    ///  * A layout for the same language (eg. "en-US" when "en-AU" is missing)
+    ///  * The fallback layout (see `set_fallback`), or the first registered layout
+    ///
+    /// Returns None only if the registry is empty.
+    pub fn resolve(&self, name: &str) -> Option<(&LayoutInfo, &Layout)> {
+        if let Some(found) = self.lookup(name) {
+            return Some(found);
+        }
+
+        let (id, _) = parse_name(name);
+        if let Some(found) = self.get(id, None) {
+            return Some(found);
+        }
+
+        let locale = normalise_locale(id);
+        let language = locale.split('-').next().unwrap_or("");
+        let same_language = self.entries.iter().find(|(info, _)| {
+            info.variant.is_none() && normalise_locale(&info.locale).split('-').next() == Some(language)
+        });
+        if let Some((info, layout)) = same_language {
+            return Some((info, layout));
+        }
+
+        self.entries.get(self.fallback.unwrap_or(0))
+            .map(|(info, layout)| (info, layout))
+    }
+}
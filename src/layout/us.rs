@@ -1,3 +1,5 @@
+use std::collections::HashMap;
+
 use keys;
 use layout::Layout;
 
@@ -121,6 +123,8 @@ pub fn us() -> Layout {
             (0, 1, keys::KEY_NUM_DECIMAL) => '.',
             (0, 0, keys::KEY_NUM_DIV) => '/',
             (0, 1, keys::KEY_NUM_DIV) => '/',
-        }
+        },
+        key_string_map: HashMap::new(),
+        key_caps_map: HashMap::new(),
     }
 }
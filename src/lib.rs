@@ -6,4 +6,18 @@ pub mod keys;
 pub mod scancode;
 pub mod layout;
 pub mod event;
+pub mod devices;
+// bitflags 0.8 expands to try!(), which is deprecated
+#[allow(deprecated)]
 pub mod state;
+pub mod hotkeys;
+pub mod ime;
+pub mod layers;
+pub mod remap;
+pub mod repeat;
+pub mod accessx;
+pub mod mousekeys;
+pub mod taphold;
+pub mod geometry;
+pub mod render;
+pub mod terminal;
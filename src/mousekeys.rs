@@ -0,0 +1,266 @@
+use std::cmp;
+use std::time::Duration;
+
+use keys::{self, Key};
+use state::{self, Modifiers};
+
+/// A mouse button
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PointerButton {
+    Left,
+    Middle,
+    Right,
+}
+
+/// An event that should be handled as if it came from a mouse
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PointerEvent {
+    /// The pointer moved by the specified number of pixels
+    Motion { dx: i32, dy: i32 },
+
+    /// A button was pressed or released
+    Button { button: PointerButton, pressed: bool },
+}
+
+/// Returns the direction that a numpad key moves the pointer in
+fn direction(key: Key) -> Option<(i32, i32)> {
+    match key {
+        keys::KEY_NUM_7 => Some((-1, -1)),
+        keys::KEY_NUM_8 => Some((0, -1)),
+        keys::KEY_NUM_9 => Some((1, -1)),
+        keys::KEY_NUM_4 => Some((-1, 0)),
+        keys::KEY_NUM_6 => Some((1, 0)),
+        keys::KEY_NUM_1 => Some((-1, 1)),
+        keys::KEY_NUM_2 => Some((0, 1)),
+        keys::KEY_NUM_3 => Some((1, 1)),
+        _ => None,
+    }
+}
+
+/// Controls the pointer with the numpad, for devices without a mouse (Mouse Keys)
+///
+/// Every key press and release should be passed to `feed` before KeyboardState. While mouse keys
+/// is turned on, the numpad keys are used as follows:
+///  * 1-4 and 6-9 move the pointer in the direction of the key, accelerating while held
+///  * 5 clicks the selected button and + double clicks it
+///  * 0 presses the selected button and . releases it (for dragging)
+///  * /, * and - select the left, middle and right button
+///
+/// Timestamps are supplied by the caller in the same way as `KeyRepeater`.
+#[derive(Debug, Clone)]
+pub struct MouseKeys {
+    /// Whether mouse keys is turned on
+    ///
+    /// default: false
+    pub enabled: bool,
+
+    /// The modifiers and key that turn mouse keys on and off, or None to disable the shortcut
+    ///
+    /// The modifiers shouldn't include the L_ and R_ flags, as either side can be used.
+    /// default: Shift+Alt+Num Lock
+    pub toggle_shortcut: Option<(Modifiers, Key)>,
+
+    /// How long a movement key must be held before the pointer starts moving continuously
+    ///
+    /// default: 160ms
+    pub delay: Duration,
+
+    /// The time between each movement
+    ///
+    /// default: 20ms
+    pub interval: Duration,
+
+    /// How long it takes the pointer to accelerate to max_speed
+    ///
+    /// default: 1s
+    pub time_to_max: Duration,
+
+    /// The number of pixels the pointer moves each interval at full speed
+    ///
+    /// Values below 1 are treated as 1, the speed the pointer starts at.
+    /// default: 20
+    pub max_speed: i32,
+
+    /// The shape of the acceleration curve. 1.0 accelerates linearly, larger values start slower
+    ///
+    /// default: 1.0
+    pub curve: f32,
+
+    /// The button that is clicked, pressed and released by the numpad keys
+    ///
+    /// default: Left
+    pub button: PointerButton,
+
+    /// The movement keys that are held, in the order they were pressed
+    held: Vec<Key>,
+
+    /// The time the pointer started moving and the time of the next movement
+    moving: Option<(Duration, Duration)>,
+
+    /// Keys whose release should not be passed on, as their press was used by mouse keys
+    swallowed: Vec<Key>,
+}
+
+impl Default for MouseKeys {
+    fn default() -> MouseKeys {
+        MouseKeys::new()
+    }
+}
+
+impl MouseKeys {
+    /// Creates a new MouseKeys instance, which is turned off
+    pub fn new() -> MouseKeys {
+        MouseKeys {
+            enabled: false,
+            toggle_shortcut: Some((state::SHIFT | state::ALT, keys::KEY_NUM_LOCK)),
+            delay: Duration::from_millis(160),
+            interval: Duration::from_millis(20),
+            time_to_max: Duration::from_millis(1000),
+            max_speed: 20,
+            curve: 1.0,
+            button: PointerButton::Left,
+            held: Vec::new(),
+            moving: None,
+            swallowed: Vec::new(),
+        }
+    }
+
+    /// Returns the time that `tick` should next be called, or None if the pointer isn't moving
+    pub fn next_deadline(&self) -> Option<Duration> {
+        self.moving.map(|(_, deadline)| deadline)
+    }
+
+    /// Returns the direction the pointer is moving in, based on the movement keys that are held
+    fn direction(&self) -> (i32, i32) {
+        let (dx, dy) = self.held.iter()
+            .filter_map(|&key| direction(key))
+            .fold((0, 0), |(x, y), (dx, dy)| (x + dx, y + dy));
+
+        (dx.clamp(-1, 1), dy.clamp(-1, 1))
+    }
+
+    /// Returns the number of pixels to move per interval after the pointer has been moving for `elapsed`
+    fn speed(&self, elapsed: Duration) -> i32 {
+        let max_speed = cmp::max(self.max_speed, 1);
+        if elapsed >= self.time_to_max {
+            return max_speed;
+        }
+
+        let progress = elapsed.as_secs_f32() / self.time_to_max.as_secs_f32();
+        let speed = 1.0 + (max_speed - 1) as f32 * progress.powf(self.curve);
+        speed.round() as i32
+    }
+
+    fn click(&self) -> Vec<PointerEvent> {
+        vec![
+            PointerEvent::Button { button: self.button, pressed: true },
+            PointerEvent::Button { button: self.button, pressed: false },
+        ]
+    }
+
+    /// Should be called whenever a key is pressed or released, with the modifiers that are held
+    ///
+    /// Returns None if the key should be passed on to KeyboardState, or the pointer events to
+    /// handle if it was used by mouse keys.
+    pub fn feed(&mut self, key: Key, pressed: bool, modifiers: Modifiers, now: Duration) -> Option<Vec<PointerEvent>> {
+        if !pressed {
+            if let Some(index) = self.swallowed.iter().position(|&swallowed| swallowed == key) {
+                self.swallowed.remove(index);
+                self.held.retain(|&held| held != key);
+                if self.held.is_empty() {
+                    self.moving = None;
+                }
+                return Some(Vec::new());
+            }
+            return None;
+        }
+
+        // Either side of each modifier can be used for the shortcut
+        if self.toggle_shortcut == Some((modifiers.side_agnostic(), key)) {
+            self.enabled = !self.enabled;
+            self.held.clear();
+            self.moving = None;
+            if !self.swallowed.contains(&key) {
+                self.swallowed.push(key);
+            }
+            return Some(Vec::new());
+        }
+
+        if !self.enabled {
+            return None;
+        }
+
+        // Hardware repeats are ignored, as tick generates the movement
+        if self.swallowed.contains(&key) {
+            return Some(Vec::new());
+        }
+
+        let events = if let Some((dx, dy)) = direction(key) {
+            self.held.push(key);
+
+            match self.moving {
+                Some(_) => Vec::new(),
+                None => {
+                    // Move by a single pixel straight away, so short taps can be used for precise movement
+                    self.moving = Some((now + self.delay, now + self.delay));
+                    vec![PointerEvent::Motion { dx, dy }]
+                }
+            }
+        } else {
+            match key {
+                keys::KEY_NUM_5 => self.click(),
+                keys::KEY_NUM_ADD => {
+                    let mut events = self.click();
+                    events.extend(self.click());
+                    events
+                }
+                keys::KEY_NUM_0 => vec![PointerEvent::Button { button: self.button, pressed: true }],
+                keys::KEY_NUM_DECIMAL => vec![PointerEvent::Button { button: self.button, pressed: false }],
+                keys::KEY_NUM_DIV => { self.button = PointerButton::Left; Vec::new() }
+                keys::KEY_NUM_MUL => { self.button = PointerButton::Middle; Vec::new() }
+                keys::KEY_NUM_SUB => { self.button = PointerButton::Right; Vec::new() }
+                _ => return None,
+            }
+        };
+
+        self.swallowed.push(key);
+        Some(events)
+    }
+
+    /// Generates the pointer movement that is due
+    ///
+    /// If the deadline was missed by more than one interval (eg. after the system was suspended),
+    /// the missed movements are folded into a single event that moves the same distance.
+    pub fn tick(&mut self, now: Duration) -> Vec<PointerEvent> {
+        let interval = cmp::max(self.interval, Duration::from_millis(1));
+
+        let (started, deadline) = match self.moving {
+            Some((started, deadline)) if deadline <= now => (started, deadline),
+            _ => return Vec::new(),
+        };
+
+        let missed = ((now - deadline).as_nanos() / interval.as_nanos()) as u64 + 1;
+        self.moving = Some((started, deadline + Duration::from_nanos(interval.as_nanos() as u64 * missed)));
+
+        let (dx, dy) = self.direction();
+        if dx == 0 && dy == 0 {
+            return Vec::new();
+        }
+
+        // Add up the speed of each missed movement, until the pointer reaches full speed
+        let mut distance: i64 = 0;
+        let mut time = deadline;
+        for remaining in (1..=missed).rev() {
+            let speed = self.speed(time - started);
+            if time - started >= self.time_to_max {
+                distance += speed as i64 * remaining as i64;
+                break;
+            }
+            distance += speed as i64;
+            time += interval;
+        }
+
+        let distance = distance.min(i32::MAX as i64) as i32;
+        vec![PointerEvent::Motion { dx: dx * distance, dy: dy * distance }]
+    }
+}
@@ -0,0 +1,96 @@
+use std::collections::HashMap;
+
+use keys::{self, Key};
+
+/// A table of physical keys that should act as other keys
+///
+/// A key can be mapped to another key, to no key at all (disabling it) or to several keys
+/// which are pressed in order and released in reverse order. Keys that aren't in the table
+/// act as themselves.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Remap {
+    pub key_map: HashMap<Key, Vec<Key>>,
+}
+
+impl Remap {
+    /// Creates an empty remap table
+    pub fn new() -> Remap {
+        Remap::default()
+    }
+
+    /// Returns true if no keys are remapped
+    pub fn is_empty(&self) -> bool {
+        self.key_map.is_empty()
+    }
+
+    /// Makes the key act as another key
+    pub fn map(&mut self, from: Key, to: Key) {
+        self.key_map.insert(from, vec![to]);
+    }
+
+    /// Makes the key act as several keys pressed together (eg. Ctrl+C)
+    pub fn map_many(&mut self, from: Key, to: &[Key]) {
+        self.key_map.insert(from, to.to_vec());
+    }
+
+    /// Makes the key do nothing
+    pub fn disable(&mut self, key: Key) {
+        self.key_map.insert(key, Vec::new());
+    }
+
+    /// Makes two keys act as each other
+    pub fn swap(&mut self, a: Key, b: Key) {
+        self.map(a, b);
+        self.map(b, a);
+    }
+
+    /// Makes the key act as itself again
+    pub fn unmap(&mut self, key: Key) {
+        self.key_map.remove(&key);
+    }
+
+    /// Adds all of the mappings from another table, replacing any existing mappings for the same keys
+    pub fn extend(&mut self, other: &Remap) {
+        for (&key, targets) in &other.key_map {
+            self.key_map.insert(key, targets.clone());
+        }
+    }
+
+    /// Returns the keys that the key acts as
+    pub fn targets(&self, key: Key) -> Vec<Key> {
+        match self.key_map.get(&key) {
+            Some(targets) => targets.clone(),
+            None => vec![key],
+        }
+    }
+}
+
+/// Caps lock acts as left control
+pub fn caps_lock_to_ctrl() -> Remap {
+    let mut remap = Remap::new();
+    remap.map(keys::KEY_CAPS_LOCK, keys::KEY_L_CTRL);
+    remap
+}
+
+/// Escape and caps lock are swapped
+pub fn swap_esc_caps_lock() -> Remap {
+    let mut remap = Remap::new();
+    remap.swap(keys::KEY_ESC, keys::KEY_CAPS_LOCK);
+    remap
+}
+
+/// Left alt and left super are swapped, to match the positions of Option and Command on a Mac keyboard
+///
+/// AltGr is left alone, as layouts that have it need it to type symbols.
+pub fn swap_alt_super() -> Remap {
+    let mut remap = Remap::new();
+    remap.swap(keys::KEY_ALT, keys::KEY_L_SUPER);
+    remap
+}
+
+/// Insert does nothing
+pub fn disable_insert() -> Remap {
+    let mut remap = Remap::new();
+    remap.disable(keys::KEY_INS);
+    remap
+}
@@ -0,0 +1,261 @@
+use std::fmt::Write;
+
+use geometry::Geometry;
+use keys::{self, Key};
+use layout::Layout;
+
+/// Returns true if the position is the continuation of a non-rectangular key
+fn is_continuation(geometry: &Geometry, index: usize) -> bool {
+    geometry.keys[..index].iter().any(|position| position.key == geometry.keys[index].key)
+}
+
+/// Returns the text to print on a key that doesn't type any symbols
+fn label(key: Key) -> &'static str {
+    match key {
+        keys::KEY_ESC => "Esc",
+        keys::KEY_BACKSPACE => "Bksp",
+        keys::KEY_TAB => "Tab",
+        keys::KEY_CAPS_LOCK => "Caps",
+        keys::KEY_ENTER => "Enter",
+        keys::KEY_L_SHIFT | keys::KEY_R_SHIFT => "Shift",
+        keys::KEY_L_CTRL | keys::KEY_R_CTRL => "Ctrl",
+        keys::KEY_ALT => "Alt",
+        keys::KEY_ALT_GR => "AltGr",
+        keys::KEY_L_SUPER | keys::KEY_R_SUPER => "Sup",
+        keys::KEY_MENU => "Menu",
+        keys::KEY_SPACE => "Space",
+        keys::KEY_INS => "Ins",
+        keys::KEY_DEL => "Del",
+        keys::KEY_HOME => "Home",
+        keys::KEY_END => "End",
+        keys::KEY_PG_UP => "PgUp",
+        keys::KEY_PG_DOWN => "PgDn",
+        keys::KEY_NUM_LOCK => "Num",
+        keys::KEY_NUM_ENTER => "Ent",
+        _ => key.name(),
+    }
+}
+
+/// Returns the printable symbol that the key types on the specified group and level
+fn symbol(layout: &Layout, group: u8, level: u8, key: Key) -> Option<String> {
+    let symbol = match layout.get_symbol(group, level, key) {
+        Some(symbol) => Some(symbol.to_string()),
+        None => layout.get_string(group, level, key).map(|string| string.to_string()),
+    };
+
+    symbol.filter(|symbol| symbol.chars().any(|c| !c.is_control() && !c.is_whitespace()))
+}
+
+/// The text printed on a keycap
+///
+/// Each corner holds the symbol of one level. Top left is level 1 and bottom left is level 0.
+/// The right hand side shows the same levels on the AltGr group.
+struct Legend {
+    top_left: Option<String>,
+    bottom_left: Option<String>,
+    top_right: Option<String>,
+    bottom_right: Option<String>,
+}
+
+impl Legend {
+    fn new(layout: &Layout, key: Key) -> Legend {
+        Legend {
+            top_left: symbol(layout, 0, 1, key),
+            bottom_left: symbol(layout, 0, 0, key),
+            top_right: symbol(layout, 1, 1, key),
+            bottom_right: symbol(layout, 1, 0, key),
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.top_left.is_none() && self.bottom_left.is_none() && self.top_right.is_none() && self.bottom_right.is_none()
+    }
+}
+
+struct Canvas {
+    lines: Vec<Vec<char>>,
+}
+
+impl Canvas {
+    fn new(width: usize, height: usize) -> Canvas {
+        Canvas {
+            lines: vec![vec![' '; width]; height],
+        }
+    }
+
+    fn put(&mut self, x: usize, y: usize, c: char) {
+        if let Some(cell) = self.lines.get_mut(y).and_then(|line| line.get_mut(x)) {
+            *cell = c;
+        }
+    }
+
+    /// Writes text starting at x, without going past max_x
+    fn write(&mut self, x: usize, y: usize, max_x: usize, text: &str) {
+        for (i, c) in text.chars().enumerate() {
+            if x + i > max_x {
+                break;
+            }
+            self.put(x + i, y, c);
+        }
+    }
+
+    /// Writes text so it ends at x, without going before min_x
+    fn write_right(&mut self, x: usize, y: usize, min_x: usize, text: &str) {
+        for (i, c) in text.chars().rev().enumerate() {
+            if i > x || x - i < min_x {
+                break;
+            }
+            self.put(x - i, y, c);
+        }
+    }
+
+    fn rect(&mut self, x: usize, y: usize, width: usize, height: usize) {
+        for i in x..x + width + 1 {
+            self.put(i, y, '-');
+            self.put(i, y + height, '-');
+        }
+        for i in y..y + height + 1 {
+            self.put(x, i, '|');
+            self.put(x + width, i, '|');
+        }
+        self.put(x, y, '+');
+        self.put(x + width, y, '+');
+        self.put(x, y + height, '+');
+        self.put(x + width, y + height, '+');
+    }
+
+    fn into_text(self) -> String {
+        let mut output = String::new();
+        for line in &self.lines {
+            let line: String = line.iter().collect();
+            output.push_str(line.trim_end());
+            output.push('\n');
+        }
+        output
+    }
+}
+
+/// Draws a keyboard as plain text, with each key showing its symbols from the layout
+///
+/// Each keycap shows the level 1 symbol in the top left corner and the level 0 symbol in the
+/// bottom left. The AltGr symbols are shown on the right hand side. Keys that don't type a symbol
+/// are labelled with their name instead.
+///
+/// Note: Gaps between rows (such as the one below the function keys) are not drawn
+pub fn render_text(layout: &Layout, geometry: &Geometry) -> String {
+    // Each quarter of a key is one column, and each row of keys is three lines
+    const QUARTER: f32 = 4.0;
+    const ROW_HEIGHT: usize = 3;
+
+    let columns = |units: f32| (units * QUARTER).round() as usize;
+    let width = columns(geometry.width());
+    let height = geometry.rows() as usize * ROW_HEIGHT;
+    let mut canvas = Canvas::new(width + 1, height + 1);
+
+    for (index, position) in geometry.keys.iter().enumerate() {
+        let x = columns(position.x);
+        let y = position.row as usize * ROW_HEIGHT;
+        let width = columns(position.width);
+        let right = x + width - 1;
+        canvas.rect(x, y, width, position.height.round() as usize * ROW_HEIGHT);
+
+        if is_continuation(geometry, index) {
+            // Remove the border between this and the part of the key above
+            for i in x + 1..right + 1 {
+                canvas.put(i, y, ' ');
+            }
+            continue;
+        }
+
+        let legend = Legend::new(layout, position.key);
+        if legend.is_empty() {
+            canvas.write(x + 1, y + 1, right, label(position.key));
+            continue;
+        }
+
+        if let Some(ref symbol) = legend.top_left {
+            canvas.write(x + 1, y + 1, right, symbol);
+        }
+        if let Some(ref symbol) = legend.bottom_left {
+            canvas.write(x + 1, y + 2, right, symbol);
+        }
+        if let Some(ref symbol) = legend.top_right {
+            canvas.write_right(right, y + 1, x + 2, symbol);
+        }
+        if let Some(ref symbol) = legend.bottom_right {
+            canvas.write_right(right, y + 2, x + 2, symbol);
+        }
+    }
+
+    canvas.into_text()
+}
+
+fn escape_xml(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Draws a keyboard as an SVG image, with each key showing its symbols from the layout
+///
+/// The keycaps are arranged in the same way as `render_text`.
+pub fn render_svg(layout: &Layout, geometry: &Geometry) -> String {
+    // Size of a key and the gap between keys, in pixels
+    const UNIT: f32 = 48.0;
+    const GAP: f32 = 2.0;
+
+    let width = geometry.width() * UNIT;
+    let height = geometry.height() * UNIT;
+
+    let mut svg = String::new();
+    writeln!(svg, "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{0}\" height=\"{1}\" viewBox=\"0 0 {0} {1}\">", width, height).unwrap();
+    writeln!(svg, "<style>.key{{fill:#f4f4f4;stroke:#404040}} text{{font-family:sans-serif;font-size:14px}} .label{{font-size:10px}} .group1{{fill:#2060c0}}</style>").unwrap();
+
+    for (index, position) in geometry.keys.iter().enumerate() {
+        if is_continuation(geometry, index) {
+            continue;
+        }
+
+        let x = position.x * UNIT + GAP;
+        let y = position.y * UNIT + GAP;
+        let w = position.width * UNIT - GAP * 2.0;
+        let h = position.height * UNIT - GAP * 2.0;
+
+        match geometry.keys[index + 1..].iter().find(|other| other.key == position.key) {
+            Some(lower) => {
+                // Draw the outline of both parts. The lower part shares its right edge with the upper part
+                let lower_x = lower.x * UNIT + GAP;
+                let lower_bottom = (lower.y + lower.height) * UNIT - GAP;
+                writeln!(svg, "<path class=\"key\" d=\"M{} {} H{} V{} H{} V{} H{} Z\"/>", x, y, x + w, lower_bottom, lower_x, y + h, x).unwrap();
+            }
+            None => {
+                writeln!(svg, "<rect class=\"key\" x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" rx=\"4\"/>", x, y, w, h).unwrap();
+            }
+        }
+
+        let legend = Legend::new(layout, position.key);
+        if legend.is_empty() {
+            writeln!(svg, "<text class=\"label\" x=\"{}\" y=\"{}\">{}</text>", x + 5.0, y + 14.0, escape_xml(label(position.key))).unwrap();
+            continue;
+        }
+
+        // Symbols are placed in the corners of the top unit of the key
+        let top = y + 17.0;
+        let bottom = y + UNIT - GAP * 2.0 - 6.0;
+        let corners = [
+            (&legend.top_left, x + 5.0, top, "start", ""),
+            (&legend.bottom_left, x + 5.0, bottom, "start", ""),
+            (&legend.top_right, x + w - 5.0, top, "end", " group1"),
+            (&legend.bottom_right, x + w - 5.0, bottom, "end", " group1"),
+        ];
+        for &(symbol, x, y, anchor, class) in &corners {
+            if let Some(ref symbol) = *symbol {
+                writeln!(svg, "<text class=\"symbol{}\" x=\"{}\" y=\"{}\" text-anchor=\"{}\">{}</text>", class, x, y, anchor, escape_xml(symbol)).unwrap();
+            }
+        }
+    }
+
+    svg.push_str("</svg>\n");
+    svg
+}
@@ -0,0 +1,100 @@
+use std::cmp;
+use std::time::Duration;
+
+use event::KeyboardEvent;
+use keys::Key;
+use state::KeyboardState;
+
+/// Generates auto-repeat events in software, for keyboards that don't repeat keys themselves
+///
+/// Every event returned by KeyboardState should be passed to `key_event`. When the last key
+/// that was pressed is held for longer than `delay`, `tick` starts returning repeat events
+/// for it every `interval`. Modifier and lock keys never repeat.
+///
+/// Timestamps are supplied by the caller. They must be monotonic but can be measured from
+/// any point in time (eg. system boot).
+#[derive(Debug, Clone)]
+pub struct KeyRepeater {
+    /// How long a key must be held before it starts repeating
+    ///
+    /// default: 600ms
+    pub delay: Duration,
+
+    /// The time between each repeat
+    ///
+    /// default: 40ms (25 repeats per second)
+    pub interval: Duration,
+
+    /// The key that is currently held and when it will next repeat
+    repeating: Option<(Key, Duration)>,
+}
+
+impl Default for KeyRepeater {
+    fn default() -> KeyRepeater {
+        KeyRepeater::new(Duration::from_millis(600), Duration::from_millis(40))
+    }
+}
+
+impl KeyRepeater {
+    /// Creates a new KeyRepeater instance
+    pub fn new(delay: Duration, interval: Duration) -> KeyRepeater {
+        KeyRepeater {
+            delay,
+            interval,
+            repeating: None,
+        }
+    }
+
+    /// Returns the key that will repeat if it continues to be held
+    pub fn repeating_key(&self) -> Option<Key> {
+        self.repeating.map(|(key, _)| key)
+    }
+
+    /// Returns the time that `tick` should next be called, or None if no key is held
+    pub fn next_deadline(&self) -> Option<Duration> {
+        self.repeating.map(|(_, deadline)| deadline)
+    }
+
+    /// Stops the current key from repeating
+    pub fn stop(&mut self) {
+        self.repeating = None;
+    }
+
+    /// Should be called with every event returned by KeyboardState
+    pub fn key_event(&mut self, event: &KeyboardEvent, now: Duration) {
+        if event.pressed {
+            // Ignore repeats from the hardware and keys that shouldn't repeat. Holding a
+            // modifier doesn't interrupt the repeating key (eg. Shift changes 'aaa' to 'AAA')
+            if !event.repeat && !event.key.is_modifier() && !event.key.is_lock() {
+                self.repeating = Some((event.key, now + self.delay));
+            }
+        } else if self.repeating_key() == Some(event.key) {
+            self.stop();
+        }
+    }
+
+    /// Generates the repeat event that is due, if there is one
+    ///
+    /// The events are generated by KeyboardState so they carry the symbol or command for the
+    /// current modifiers. At most one event is returned, even if several deadlines were missed
+    /// (eg. after the system was suspended), and the next deadline is the first one after `now`.
+    pub fn tick(&mut self, state: &mut KeyboardState, now: Duration) -> Vec<KeyboardEvent> {
+        let interval = cmp::max(self.interval, Duration::from_millis(1));
+
+        let (key, deadline) = match self.repeating {
+            Some((key, deadline)) if deadline <= now => (key, deadline),
+            _ => return Vec::new(),
+        };
+
+        // The release event may have been missed
+        if !state.key_pressed(key) {
+            self.stop();
+            return Vec::new();
+        }
+
+        let late = Duration::from_nanos(((now - deadline).as_nanos() % interval.as_nanos()) as u64);
+        self.repeating = Some((key, now + interval - late));
+
+        vec![state.actuate_remapped_key_at(key, true, now)]
+    }
+}
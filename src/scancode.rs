@@ -44,9 +44,9 @@ pub fn scancode_to_key(escaped: bool, scancode: u8) -> Option<Key> {
         (false, 0x26) => Some(keys::KEY_L),
         (false, 0x27) => Some(keys::KEY_COLON),
         (false, 0x28) => Some(keys::KEY_QUOTE),
-        (false, 0x29) => Some(keys::KEY_HASH), // CHECKME
+        (false, 0x29) => Some(keys::KEY_BACKTICK),
         (false, 0x2A) => Some(keys::KEY_L_SHIFT),
-        (false, 0x2B) => Some(keys::KEY_BACKTICK), // CHECKME
+        (false, 0x2B) => Some(keys::KEY_HASH), // Above enter on ANSI, left of enter on ISO
         (false, 0x2C) => Some(keys::KEY_Z),
         (false, 0x2D) => Some(keys::KEY_X),
         (false, 0x2E) => Some(keys::KEY_C),
@@ -98,10 +98,15 @@ pub fn scancode_to_key(escaped: bool, scancode: u8) -> Option<Key> {
         (true, 0x52) => Some(keys::KEY_INS),
         (false, 0x53) => Some(keys::KEY_NUM_DECIMAL),
         (true, 0x53) => Some(keys::KEY_DEL),
-        (false, 0x54) => None, // KEY_SYS_RQ
-        (false, 0x56) => None, // KEY_B_SLASH (GB only)
+        (false, 0x54) => Some(keys::KEY_SYS_RQ), // Alt+Print Screen
+        (false, 0x56) => Some(keys::KEY_B_SLASH), // ISO only
         (false, 0x57) => Some(keys::KEY_F11),
         (false, 0x58) => Some(keys::KEY_F12),
+        (false, 0x70) => Some(keys::KEY_KATAKANA_HIRAGANA),  // JIS only
+        (false, 0x73) => Some(keys::KEY_RO),  // JIS only
+        (false, 0x79) => Some(keys::KEY_HENKAN),  // JIS only
+        (false, 0x7B) => Some(keys::KEY_MUHENKAN),  // JIS only
+        (false, 0x7D) => Some(keys::KEY_YEN),  // JIS only
         (true, 0x5B) => Some(keys::KEY_L_SUPER),  // PC only
         (true, 0x5C) => Some(keys::KEY_R_SUPER),  // PC only
         (true, 0x5D) => Some(keys::KEY_MENU),  // PC only
@@ -109,7 +114,7 @@ pub fn scancode_to_key(escaped: bool, scancode: u8) -> Option<Key> {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Default)]
 pub struct ScancodeReader {
     escaped: bool,
 }
@@ -138,12 +143,7 @@ impl ScancodeReader {
 
         self.escaped = false;
 
-        if let Some(key) = key {
-            Some((key, released))
-        } else {
-            // TODO Warning
-
-            None
-        }
+        // TODO Warning when the scancode is unknown
+        key.map(|key| (key, released))
     }
 }
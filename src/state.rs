@@ -1,22 +1,61 @@
+use std::collections::HashMap;
+use std::time::Duration;
+
 use fixedbitset::FixedBitSet;
 
 use keys::{self, KeyType, Key};
-use layout::Layout;
-use event::{KeyboardEvent, LogicalKeyboardEvent};
+use layout::{CapsBehaviour, Layout};
+use event::{KeyboardEvent, LogicalKeyboardEvent, PrivilegedEvent};
+use ime::{InputMethod, InputMethodAction};
+use remap::Remap;
 
 bitflags! {
-    pub flags Modifiers: u8 {
+    /// The modifiers that are held
+    ///
+    /// SHIFT, CTRL, ALT and SUPER are set when either side is held. The L_ and R_ flags are set
+    /// as well to tell which side it is. ALT_GR has no side. R_ALT is only used when the layout
+    /// doesn't have an AltGr key, so that key acts as a right alt key.
+    pub flags Modifiers: u16 {
         const SHIFT = 1,
         const CTRL = 1 << 1,
         const ALT = 1 << 2,
         const ALT_GR = 1 << 3,
         const SUPER = 1 << 4,
+        const L_SHIFT = 1 << 5,
+        const R_SHIFT = 1 << 6,
+        const L_CTRL = 1 << 7,
+        const R_CTRL = 1 << 8,
+        const L_ALT = 1 << 9,
+        const R_ALT = 1 << 10,
+        const L_SUPER = 1 << 11,
+        const R_SUPER = 1 << 12,
     }
 }
 
 impl Modifiers {
+    /// Returns the SHIFT, CTRL, ALT, ALT_GR and SUPER flags. Use `bits` to include the L_ and R_ flags
     pub fn to_bits(&self) -> u8 {
-        self.bits
+        self.side_agnostic().bits as u8
+    }
+
+    /// Returns the modifiers without the L_ and R_ flags
+    pub fn side_agnostic(&self) -> Modifiers {
+        *self & (SHIFT | CTRL | ALT | ALT_GR | SUPER)
+    }
+
+    /// Returns only the L_ and R_ flags
+    pub fn sides(&self) -> Modifiers {
+        *self - self.side_agnostic()
+    }
+
+    /// Sets SHIFT, CTRL, ALT and SUPER to match their L_ and R_ flags
+    ///
+    /// This is needed after removing one side of a modifier, as the other side may still be set.
+    pub fn sync_sides(&mut self) {
+        for &(modifier, sides) in &[(SHIFT, L_SHIFT | R_SHIFT), (CTRL, L_CTRL | R_CTRL), (ALT, L_ALT | R_ALT), (SUPER, L_SUPER | R_SUPER)] {
+            let held = self.intersects(sides);
+            self.set(modifier, held);
+        }
     }
 }
 
@@ -28,6 +67,49 @@ bitflags! {
     }
 }
 
+impl LockKeyState {
+    /// Returns the LED byte to send to a PS/2 keyboard after the Set LEDs command (0xED)
+    pub fn to_ps2_leds(&self) -> u8 {
+        (self.contains(SCROLL_LOCK) as u8) | (self.contains(NUM_LOCK) as u8) << 1 | (self.contains(CAPS_LOCK) as u8) << 2
+    }
+
+    /// Reads the LED byte of a PS/2 keyboard
+    pub fn from_ps2_leds(leds: u8) -> LockKeyState {
+        let mut state = LockKeyState::empty();
+        state.set(SCROLL_LOCK, leds & 1 != 0);
+        state.set(NUM_LOCK, leds & (1 << 1) != 0);
+        state.set(CAPS_LOCK, leds & (1 << 2) != 0);
+        state
+    }
+
+    /// Returns the LED output report to send to a USB HID keyboard
+    pub fn to_hid_leds(&self) -> u8 {
+        (self.contains(NUM_LOCK) as u8) | (self.contains(CAPS_LOCK) as u8) << 1 | (self.contains(SCROLL_LOCK) as u8) << 2
+    }
+
+    /// Reads the LED output report of a USB HID keyboard
+    pub fn from_hid_leds(leds: u8) -> LockKeyState {
+        let mut state = LockKeyState::empty();
+        state.set(NUM_LOCK, leds & 1 != 0);
+        state.set(CAPS_LOCK, leds & (1 << 1) != 0);
+        state.set(SCROLL_LOCK, leds & (1 << 2) != 0);
+        state
+    }
+}
+
+/// Selects how the caps lock key behaves
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CapsLockMode {
+    /// The caps lock toggles and affects each key as described by the layout
+    CapsLock,
+
+    /// The caps lock toggles and acts like the shift key on every key that has symbols
+    ShiftLock,
+
+    /// The caps lock does not toggle and acts as a shift key while it is held
+    Shift,
+}
+
 #[derive(Debug, Clone)]
 pub struct KeyboardState {
     /// Tracks the physical state of each key
@@ -44,6 +126,67 @@ pub struct KeyboardState {
     /// This allows the toggling to be disabled so the keys could be used for something else (eg a full screen game)
     /// default: true
     pub lock_keys_enabled: bool,
+
+    /// Selects how the caps lock key behaves
+    ///
+    /// default: CapsLockMode::CapsLock
+    pub caps_lock_mode: CapsLockMode,
+
+    /// When set to true, tapping a modifier key latches it until the next key is pressed (Sticky Keys)
+    ///
+    /// Tapping a modifier twice locks it until it is tapped again.
+    /// default: false
+    pub sticky_keys_enabled: bool,
+
+    /// When set to true, pressing the shift key five times in a row toggles sticky keys
+    ///
+    /// default: false
+    pub sticky_keys_shortcut: bool,
+
+    /// Modifiers that have been latched by sticky keys and will apply to the next key press
+    pub latched_modifiers: Modifiers,
+
+    /// Modifiers that have been locked by sticky keys
+    pub locked_modifiers: Modifiers,
+
+    /// When set to true, Ctrl+Shift+U starts typing a unicode code point in hexadecimal
+    ///
+    /// The digits are ended with Space or Enter, which types the symbol, or Escape (or Backspace
+    /// with no digits) to cancel.
+    /// default: false
+    pub unicode_input_enabled: bool,
+
+    /// The hex digits typed so far, while a code point is being typed
+    unicode_input: Option<String>,
+
+    /// When set to true, Ctrl+Alt+Delete and Alt+SysRq+<key> are reported as privileged events
+    ///
+    /// These bypass the input method and Unicode input, so that they always reach the system.
+    /// default: false
+    pub privileged_keys_enabled: bool,
+
+    /// The input method that key presses are passed through before they emit symbols
+    ///
+    /// default: None
+    pub input_method: Option<Box<dyn InputMethod>>,
+
+    /// Physical keys that act as other keys, applied by `actuate_key` and `actuate_physical_key`
+    ///
+    /// default: empty
+    pub remap: Remap,
+
+    /// The keys each held physical key was remapped to when it was pressed (or itself, if it
+    /// wasn't remapped), by keycode
+    remapped_keys: HashMap<u8, Vec<Key>>,
+
+    /// The times that the pressed keys were pressed, if they were given
+    pressed_at: HashMap<u8, Duration>,
+
+    /// Modifiers that are held but have not been used with another key yet
+    sticky_pending: Modifiers,
+
+    /// The number of times shift has been pressed in a row, for the sticky keys shortcut
+    shift_press_count: u8,
 }
 
 impl KeyboardState {
@@ -52,8 +195,22 @@ impl KeyboardState {
         KeyboardState {
             pressed_keys: FixedBitSet::with_capacity(256),
             lock_keys: LockKeyState::empty(),
-            layout: layout,
+            layout,
             lock_keys_enabled: true,
+            caps_lock_mode: CapsLockMode::CapsLock,
+            sticky_keys_enabled: false,
+            sticky_keys_shortcut: false,
+            latched_modifiers: Modifiers::empty(),
+            locked_modifiers: Modifiers::empty(),
+            sticky_pending: Modifiers::empty(),
+            shift_press_count: 0,
+            unicode_input_enabled: false,
+            unicode_input: None,
+            privileged_keys_enabled: false,
+            input_method: None,
+            remap: Remap::new(),
+            remapped_keys: HashMap::new(),
+            pressed_at: HashMap::new(),
         }
     }
 
@@ -62,32 +219,113 @@ impl KeyboardState {
         self.pressed_keys.contains(key.keycode() as usize)
     }
 
-    /// Returns true if either shift key is pressed
+    /// Returns when the key was pressed, if it is pressed and was given a time by `actuate_key_at`
+    pub fn pressed_at(&self, key: Key) -> Option<Duration> {
+        self.pressed_at.get(&key.keycode()).cloned()
+    }
+
+    /// Returns the modifiers that are latched or locked by sticky keys
+    pub fn sticky_modifiers(&self) -> Modifiers {
+        if self.sticky_keys_enabled {
+            self.latched_modifiers | self.locked_modifiers
+        } else {
+            Modifiers::empty()
+        }
+    }
+
+    /// Returns the modifier that the specified key controls, including the flag for its side
+    pub fn key_modifier(&self, key: Key) -> Option<Modifiers> {
+        match key {
+            keys::KEY_L_SHIFT => Some(SHIFT | L_SHIFT),
+            keys::KEY_R_SHIFT => Some(SHIFT | R_SHIFT),
+            keys::KEY_L_CTRL => Some(CTRL | L_CTRL),
+            keys::KEY_R_CTRL => Some(CTRL | R_CTRL),
+            keys::KEY_ALT => Some(ALT | L_ALT),
+            keys::KEY_ALT_GR => if self.layout.has_alt_gr_key { Some(ALT_GR) } else { Some(ALT | R_ALT) },
+            keys::KEY_L_SUPER => Some(SUPER | L_SUPER),
+            keys::KEY_R_SUPER => Some(SUPER | R_SUPER),
+            _ => None,
+        }
+    }
+
+    /// Returns true if the key that controls the modifier is pressed, or the modifier is latched/locked by sticky keys
+    fn side_held(&self, key: Key, modifier: Modifiers) -> bool {
+        self.key_pressed(key) || self.sticky_modifiers().contains(modifier)
+    }
+
+    /// Returns true if the left shift key is pressed (or latched/locked by sticky keys)
+    pub fn l_shift(&self) -> bool {
+        self.side_held(keys::KEY_L_SHIFT, L_SHIFT)
+    }
+
+    /// Returns true if the right shift key is pressed (or latched/locked by sticky keys)
+    pub fn r_shift(&self) -> bool {
+        self.side_held(keys::KEY_R_SHIFT, R_SHIFT)
+    }
+
+    /// Returns true if the left control key is pressed (or latched/locked by sticky keys)
+    pub fn l_ctrl(&self) -> bool {
+        self.side_held(keys::KEY_L_CTRL, L_CTRL)
+    }
+
+    /// Returns true if the right control key is pressed (or latched/locked by sticky keys)
+    pub fn r_ctrl(&self) -> bool {
+        self.side_held(keys::KEY_R_CTRL, R_CTRL)
+    }
+
+    /// Returns true if the alt key is pressed (or latched/locked by sticky keys)
+    pub fn l_alt(&self) -> bool {
+        self.side_held(keys::KEY_ALT, L_ALT)
+    }
+
+    /// Returns true if the alt gr key is pressed and acting as an alt key (or latched/locked by sticky keys)
+    pub fn r_alt(&self) -> bool {
+        !self.layout.has_alt_gr_key && self.side_held(keys::KEY_ALT_GR, R_ALT)
+    }
+
+    /// Returns true if the left super key is pressed (or latched/locked by sticky keys)
+    pub fn l_super(&self) -> bool {
+        self.side_held(keys::KEY_L_SUPER, L_SUPER)
+    }
+
+    /// Returns true if the right super key is pressed (or latched/locked by sticky keys)
+    pub fn r_super(&self) -> bool {
+        self.side_held(keys::KEY_R_SUPER, R_SUPER)
+    }
+
+    /// Returns true if either shift key is pressed (or shift is latched/locked by sticky keys)
+    ///
+    /// Note: If caps_lock_mode is set to Shift, the caps lock key is also treated as a shift key
     pub fn shift(&self) -> bool {
         self.key_pressed(keys::KEY_L_SHIFT) | self.key_pressed(keys::KEY_R_SHIFT)
+            || (self.caps_lock_mode == CapsLockMode::Shift && self.key_pressed(keys::KEY_CAPS_LOCK))
+            || self.sticky_modifiers().contains(SHIFT)
     }
 
-    /// Returns true if either control key is pressed
+    /// Returns true if either control key is pressed (or ctrl is latched/locked by sticky keys)
     pub fn ctrl(&self) -> bool {
         self.key_pressed(keys::KEY_L_CTRL) | self.key_pressed(keys::KEY_R_CTRL)
+            || self.sticky_modifiers().contains(CTRL)
     }
 
-    /// Returns true if the alt key is pressed
+    /// Returns true if the alt key is pressed (or alt is latched/locked by sticky keys)
     /// Note: If has_alt_gr_key is false, that key would be treated as an alt key instead
     pub fn alt(&self) -> bool {
         self.key_pressed(keys::KEY_ALT) || (!self.layout.has_alt_gr_key && self.key_pressed(keys::KEY_ALT_GR))
+            || self.sticky_modifiers().contains(ALT)
     }
 
-    /// Returns true if either super key is pressed
+    /// Returns true if either super key is pressed (or super is latched/locked by sticky keys)
     ///
     /// Note: The super keys are also known as the "Windows key" (Windows) or "Command key" (Macintosh)
     pub fn sup(&self) -> bool {
         self.key_pressed(keys::KEY_L_SUPER) | self.key_pressed(keys::KEY_R_SUPER)
+            || self.sticky_modifiers().contains(SUPER)
     }
 
-    /// Returns true if the alt gr (Alternate Graphic) key is pressed
+    /// Returns true if the alt gr (Alternate Graphic) key is pressed (or latched/locked by sticky keys)
     pub fn alt_gr(&self) -> bool {
-        self.layout.has_alt_gr_key && self.key_pressed(keys::KEY_ALT_GR)
+        self.layout.has_alt_gr_key && (self.key_pressed(keys::KEY_ALT_GR) || self.sticky_modifiers().contains(ALT_GR))
     }
 
     /// Returns a bitflags object of logical modifier states
@@ -98,6 +336,14 @@ impl KeyboardState {
         if self.alt() { modifiers.insert(ALT) }
         if self.sup() { modifiers.insert(SUPER) }
         if self.alt_gr() { modifiers.insert(ALT_GR) }
+        if self.l_shift() { modifiers.insert(L_SHIFT) }
+        if self.r_shift() { modifiers.insert(R_SHIFT) }
+        if self.l_ctrl() { modifiers.insert(L_CTRL) }
+        if self.r_ctrl() { modifiers.insert(R_CTRL) }
+        if self.l_alt() { modifiers.insert(L_ALT) }
+        if self.r_alt() { modifiers.insert(R_ALT) }
+        if self.l_super() { modifiers.insert(L_SUPER) }
+        if self.r_super() { modifiers.insert(R_SUPER) }
         modifiers
     }
 
@@ -116,6 +362,61 @@ impl KeyboardState {
         self.lock_keys.contains(SCROLL_LOCK)
     }
 
+    /// Sets the state of the lock keys, eg. to match the LEDs of the keyboard when it is connected
+    pub fn set_lock_keys(&mut self, lock_keys: LockKeyState) {
+        self.lock_keys = lock_keys;
+    }
+
+    /// Returns the hex digits typed so far, or None if a code point isn't being typed
+    ///
+    /// This should be shown to the user while typing (eg. as an underlined "u1f6").
+    pub fn unicode_input(&self) -> Option<&str> {
+        self.unicode_input.as_deref()
+    }
+
+    /// Handles a key press while a code point is being typed, returning the logical event to emit instead
+    ///
+    /// Every key is consumed, except modifiers which still need to update the state and lock keys
+    /// which keep their logical event.
+    fn actuate_unicode_input(&mut self, key: Key, logical: Option<LogicalKeyboardEvent>) -> Option<LogicalKeyboardEvent> {
+        let group = self.get_group();
+        let digits = match self.unicode_input {
+            Some(ref mut digits) => digits,
+            None => return logical,
+        };
+
+        match key {
+            keys::KEY_CAPS_LOCK | keys::KEY_NUM_LOCK | keys::KEY_SCROLL_LOCK => return logical,
+            keys::KEY_ESC => self.unicode_input = None,
+            keys::KEY_BACKSPACE => {
+                if digits.pop().is_none() {
+                    self.unicode_input = None;
+                }
+            }
+            keys::KEY_SPACE | keys::KEY_ENTER | keys::KEY_NUM_ENTER => {
+                let symbol = u32::from_str_radix(digits, 16).ok().and_then(char::from_u32);
+                self.unicode_input = None;
+                return symbol.map(LogicalKeyboardEvent::Symbol);
+            }
+            _ => {
+                // The digits can be typed while Ctrl+Shift is still held
+                let symbol = match logical {
+                    Some(LogicalKeyboardEvent::Symbol(symbol)) => Some(symbol),
+                    Some(LogicalKeyboardEvent::Command(_, key)) => self.layout.get_symbol(group, 0, key),
+                    _ => None,
+                };
+
+                match symbol {
+                    // The largest code point has 6 digits, but leading zeros are allowed
+                    Some(digit) if digit.is_ascii_hexdigit() && digits.len() < 8 => digits.push(digit.to_ascii_lowercase()),
+                    _ => {}
+                }
+            }
+        }
+
+        None
+    }
+
     /// Returns the currently selected key group
     ///
     /// Note: Only AltGr based group switching has been implemented so far
@@ -123,31 +424,279 @@ impl KeyboardState {
         if self.alt_gr() { 1 } else { 0 }
     }
 
-    /// Returns the level that is selected on the specified key type
+    /// Returns the level that is selected on the specified key
     ///
     /// Note: The symbol/command of each level is indicated by row on the keytop
     ///
     /// Different key types need to behave differently with regards to level switching:
-    ///  * Alphabetic keys can be shifted by using either the shift key or caps lock (when both
-    ///    used at the same time, they will negate each other)
-    ///  * NumeralsAndPunctuation keys are not shifted by the caps lock
+    ///  * Alphabetic, Numeric and Punctuation keys are shifted by the shift key. How the caps
+    ///    lock affects them is decided by the layout (see `Layout::caps_behaviour`) and the
+    ///    caps_lock_mode. By default, only alphabetic keys are shifted by the caps lock (when
+    ///    both are used at the same time, they will negate each other)
     ///  * Numpad keys are shifted by the num lock only
-    pub fn get_key_level(&self, key_type: &KeyType) -> u8 {
-        let shifted = match *key_type {
-            KeyType::Alphabetic => self.shift() ^ self.caps_lock(),
-            KeyType::Numeric | KeyType::Punctuation => self.shift(),
-            KeyType::Numpad => !self.num_lock(),
-            KeyType::Control => false,
+    pub fn get_key_level(&self, key: Key) -> u8 {
+        let caps_behaviour = match self.caps_lock_mode {
+            CapsLockMode::CapsLock => self.layout.caps_behaviour(key),
+            CapsLockMode::ShiftLock => CapsBehaviour::ShiftLock,
+            CapsLockMode::Shift => CapsBehaviour::Unaffected,
+        };
+
+        let shifted = match key.key_type() {
+            Some(KeyType::Alphabetic) | Some(KeyType::Numeric) | Some(KeyType::Punctuation) => {
+                match caps_behaviour {
+                    CapsBehaviour::Alphabetic => self.shift() ^ self.caps_lock(),
+                    CapsBehaviour::ShiftLock => self.shift() || self.caps_lock(),
+                    CapsBehaviour::Unaffected => self.shift(),
+                }
+            }
+            Some(KeyType::Numpad) => !self.num_lock(),
+            Some(KeyType::Control) | None => false,
         };
 
         if shifted { 1 } else { 0 }
     }
 
+    /// Looks up the symbol of a key in the layout and returns it as a logical event
+    fn get_symbol_event(&self, group: u8, level: u8, key: Key) -> Option<LogicalKeyboardEvent> {
+        if let Some(symbol) = self.layout.get_symbol(group, level, key) {
+            Some(LogicalKeyboardEvent::Symbol(symbol))
+        } else {
+            self.layout.get_string(group, level, key).map(|string| LogicalKeyboardEvent::Text(string.to_string()))
+        }
+    }
+
+    /// Updates the latched and locked modifiers of sticky keys
+    ///
+    /// This is called before the key is handled so the sticky keys shortcut can be detected.
+    /// Returns an event if the key changed the sticky keys state.
+    fn update_sticky_keys(&mut self, key: Key, pressed: bool, repeat: bool) -> Option<LogicalKeyboardEvent> {
+        if pressed && !repeat && self.sticky_keys_shortcut {
+            if key == keys::KEY_L_SHIFT || key == keys::KEY_R_SHIFT {
+                self.shift_press_count += 1;
+
+                if self.shift_press_count == 5 {
+                    self.shift_press_count = 0;
+                    self.sticky_keys_enabled = !self.sticky_keys_enabled;
+                    self.latched_modifiers = Modifiers::empty();
+                    self.locked_modifiers = Modifiers::empty();
+                    self.sticky_pending = Modifiers::empty();
+                    return Some(LogicalKeyboardEvent::StickyKeys(self.sticky_keys_enabled));
+                }
+            } else {
+                self.shift_press_count = 0;
+            }
+        }
+
+        if !self.sticky_keys_enabled || repeat {
+            return None;
+        }
+
+        match self.key_modifier(key) {
+            Some(modifier) if pressed => self.sticky_pending.insert(modifier),
+            // The modifier was tapped without being used with another key
+            Some(modifier) if self.sticky_pending.contains(modifier) => {
+                self.sticky_pending.remove(modifier);
+                self.sticky_pending.sync_sides();
+
+                // The other side of the modifier may still be latched or locked
+                if self.locked_modifiers.contains(modifier) {
+                    self.locked_modifiers.remove(modifier);
+                } else if self.latched_modifiers.contains(modifier) {
+                    self.latched_modifiers.remove(modifier);
+                    self.locked_modifiers.insert(modifier);
+                } else {
+                    self.latched_modifiers.insert(modifier);
+                }
+                self.latched_modifiers.sync_sides();
+                self.locked_modifiers.sync_sides();
+
+                return Some(LogicalKeyboardEvent::StickyModifiers {
+                    latched: self.latched_modifiers,
+                    locked: self.locked_modifiers,
+                });
+            }
+            Some(_) => {}
+            None if pressed => self.sticky_pending = Modifiers::empty(),
+            None => {}
+        }
+
+        None
+    }
+
+    /// Releases every key that is pressed, eg. when the window loses focus or the keyboard is unplugged
+    ///
+    /// Returns a release event for each key, in ascending keycode order. Lock keys and keys
+    /// latched or locked by sticky keys are not affected.
+    pub fn release_all(&mut self) -> Vec<KeyboardEvent> {
+        let pressed: Vec<Key> = self.pressed_keys.ones().map(|keycode| Key::from_keycode(keycode as u8)).collect();
+
+        self.remapped_keys.clear();
+        self.sticky_pending = Modifiers::empty();
+        pressed.into_iter().map(|key| self.actuate_remapped_key(key, false)).collect()
+    }
+
+    /// Updates the pressed keys to match a list of physical keys that are known to be pressed (eg. from a HID report)
+    ///
+    /// The keys are remapped by `remap` like keys given to `actuate_key`. Returns an event for
+    /// each key that changed, with the releases first and then the presses, each in ascending
+    /// keycode order of the physical keys. The keys were pressed while the state wasn't being
+    /// told about them, so the press events don't have a logical event and don't toggle lock keys.
+    pub fn sync_pressed(&mut self, keys: &[Key]) -> Vec<KeyboardEvent> {
+        let mut pressed = FixedBitSet::with_capacity(256);
+        for key in keys {
+            pressed.insert(key.keycode() as usize);
+        }
+
+        // Keys pressed without going through the remap (eg. by actuate_remapped_key) act as themselves
+        let unmapped: Vec<Key> = self.pressed_keys.ones()
+            .map(|keycode| Key::from_keycode(keycode as u8))
+            .filter(|key| !self.remapped_keys.values().any(|targets| targets.contains(key)))
+            .collect();
+        for key in unmapped {
+            self.remapped_keys.entry(key.keycode()).or_default().push(key);
+        }
+
+        let mut held: Vec<u8> = self.remapped_keys.keys().cloned().collect();
+        held.sort();
+
+        let released: Vec<Key> = held.iter()
+            .filter(|&&keycode| !pressed.contains(keycode as usize))
+            .map(|&keycode| Key::from_keycode(keycode))
+            .collect();
+        let mut events = self.release_physical_keys(&released);
+
+        let new: Vec<usize> = pressed.ones().filter(|&keycode| !self.remapped_keys.contains_key(&(keycode as u8))).collect();
+        for keycode in new {
+            for target in self.remap_targets(Key::from_keycode(keycode as u8), true) {
+                self.pressed_keys.insert(target.keycode() as usize);
+                self.pressed_at.remove(&target.keycode());
+                events.push(KeyboardEvent {
+                    key: target,
+                    pressed: true,
+                    repeat: false,
+                    logical: None,
+                    timestamp: None,
+                        held_for: None,
+                    lock_keys: None,
+                    privileged: None,
+                });
+            }
+        }
+
+        events
+    }
+
+    /// Releases physical keys through the remap, eg. when the keyboard holding them is unplugged
+    ///
+    /// Keys that aren't held are ignored, and released modifiers aren't latched by sticky keys.
+    pub fn release_physical_keys(&mut self, keys: &[Key]) -> Vec<KeyboardEvent> {
+        let held: Vec<Key> = keys.iter().cloned().filter(|key| self.remapped_keys.contains_key(&key.keycode())).collect();
+
+        self.sticky_pending = Modifiers::empty();
+        held.into_iter().flat_map(|key| self.actuate_through_remap(key, false, None)).collect()
+    }
+
+    /// Same as `actuate_key`, but returns an event for every key that the key is remapped to
+    ///
+    /// Keys that are mapped to several keys press them in order and release them in reverse order.
+    pub fn actuate_physical_key(&mut self, key: Key, pressed: bool) -> Vec<KeyboardEvent> {
+        self.actuate_through_remap(key, pressed, None)
+    }
+
+    /// Actuates the keys that the physical key is remapped to
+    ///
+    /// The keys are remembered when the physical key is pressed, so its release still releases
+    /// the same keys if `remap` changes while it is held. A key that several held physical keys
+    /// are remapped to is pressed by the first of them and released by the last.
+    fn actuate_through_remap(&mut self, key: Key, pressed: bool, timestamp: Option<Duration>) -> Vec<KeyboardEvent> {
+        self.remap_targets(key, pressed).into_iter()
+            .map(|target| self.actuate_unmapped_key(target, pressed, timestamp))
+            .collect()
+    }
+
+    /// Records the physical key as pressed or released, returning the keys it should actuate
+    fn remap_targets(&mut self, key: Key, pressed: bool) -> Vec<Key> {
+        if pressed {
+            // Repeats of the physical key repeat all of its keys
+            let repeat = self.remapped_keys.contains_key(&key.keycode());
+            let remap = &self.remap;
+            let targets = self.remapped_keys.entry(key.keycode())
+                .or_insert_with(|| remap.targets(key))
+                .clone();
+
+            targets.into_iter().filter(|&target| repeat || !self.key_pressed(target)).collect()
+        } else {
+            let targets = self.remapped_keys.remove(&key.keycode())
+                .unwrap_or_else(|| self.remap.targets(key));
+
+            targets.into_iter().rev()
+                .filter(|target| !self.remapped_keys.values().any(|other| other.contains(target)))
+                .collect()
+        }
+    }
+
+    /// Returns the privileged event that pressing the key triggers, if there is one
+    fn privileged_event(&self, key: Key) -> Option<PrivilegedEvent> {
+        let delete = key == keys::KEY_DEL || (key == keys::KEY_NUM_DECIMAL && !self.num_lock());
+        if delete && self.ctrl() && self.alt() {
+            return Some(PrivilegedEvent::SecureAttention);
+        }
+
+        let sys_rq_held = self.key_pressed(keys::KEY_SYS_RQ) || self.key_pressed(keys::KEY_PRT_SCR);
+        let is_sys_rq = key == keys::KEY_SYS_RQ || key == keys::KEY_PRT_SCR;
+        if sys_rq_held && !is_sys_rq && self.alt() && self.key_modifier(key).is_none() {
+            return Some(PrivilegedEvent::SysRq(key));
+        }
+
+        None
+    }
+
     /// Should be called whenever a key is pressed or released
     ///
-    /// This alters the state and returns a KeyboardEvent
+    /// This alters the state and returns a KeyboardEvent. The key is first remapped by `remap`.
+    /// If it is mapped to several keys, they are all actuated and the event of the last one is
+    /// returned (`actuate_physical_key` returns all of them). If no key is actuated (eg. the key
+    /// is disabled), the returned event is for the key itself and has no logical event.
     pub fn actuate_key(&mut self, key: Key, pressed: bool) -> KeyboardEvent {
+        self.actuate_key_with_time(key, pressed, None)
+    }
+
+    /// Same as `actuate_key`, with the time that the key was pressed or released
+    ///
+    /// The time is monotonic and supplied by the caller (eg. the time since boot). It is
+    /// returned in the event, and releases also return how long the key was held.
+    pub fn actuate_key_at(&mut self, key: Key, pressed: bool, now: Duration) -> KeyboardEvent {
+        self.actuate_key_with_time(key, pressed, Some(now))
+    }
+
+    /// Same as `actuate_key`, but the key is not remapped
+    ///
+    /// This is for keys that have already been remapped, such as keys from `pressed_keys`.
+    pub fn actuate_remapped_key(&mut self, key: Key, pressed: bool) -> KeyboardEvent {
+        self.actuate_unmapped_key(key, pressed, None)
+    }
+
+    /// Same as `actuate_remapped_key`, with the time that the key was pressed or released
+    pub fn actuate_remapped_key_at(&mut self, key: Key, pressed: bool, now: Duration) -> KeyboardEvent {
+        self.actuate_unmapped_key(key, pressed, Some(now))
+    }
+
+    fn actuate_key_with_time(&mut self, key: Key, pressed: bool, timestamp: Option<Duration>) -> KeyboardEvent {
+        self.actuate_through_remap(key, pressed, timestamp).pop().unwrap_or(KeyboardEvent {
+            key,
+            pressed,
+            repeat: false,
+            logical: None,
+            timestamp,
+            held_for: None,
+            lock_keys: None,
+            privileged: None,
+        })
+    }
+
+    fn actuate_unmapped_key(&mut self, key: Key, pressed: bool, timestamp: Option<Duration>) -> KeyboardEvent {
         let mut logical = None;
+        let mut lock_keys = None;
 
         let mut repeat = false;
         if pressed {
@@ -164,6 +713,24 @@ impl KeyboardState {
             }
         }
 
+        let held_for = if pressed {
+            if !repeat {
+                match timestamp {
+                    Some(now) => self.pressed_at.insert(key.keycode(), now),
+                    None => self.pressed_at.remove(&key.keycode()),
+                };
+            }
+            None
+        } else {
+            let pressed_at = self.pressed_at.remove(&key.keycode());
+            match (pressed_at, timestamp) {
+                (Some(pressed_at), Some(now)) => Some(now.saturating_sub(pressed_at)),
+                _ => None,
+            }
+        };
+
+        let sticky_event = self.update_sticky_keys(key, pressed, repeat);
+
         if let Some(key_type) = key.key_type() {
             match key_type {
                 KeyType::Alphabetic | KeyType::Numeric | KeyType::Punctuation => {
@@ -176,25 +743,31 @@ impl KeyboardState {
                             // Get symbol from layout and return it if one exists
                             // This handles general typing
                             let group = self.get_group();
-                            let key_level = self.get_key_level(&key_type);
+                            let key_level = self.get_key_level(key);
 
-                            if let Some(symbol) = self.layout.get_symbol(group, key_level, key) {
-                                logical = Some(LogicalKeyboardEvent::Symbol(symbol));
-                            }
+                            logical = self.get_symbol_event(group, key_level, key);
                         }
                     }
                 }
                 KeyType::Control => {
                     if pressed {
-                        if self.lock_keys_enabled {
+                        let old_lock_keys = self.lock_keys;
+
+                        // Holding a lock key shouldn't keep toggling it
+                        if self.lock_keys_enabled && !repeat {
                             match key {
-                                keys::KEY_CAPS_LOCK => self.lock_keys.toggle(CAPS_LOCK),
+                                keys::KEY_CAPS_LOCK if self.caps_lock_mode != CapsLockMode::Shift => {
+                                    self.lock_keys.toggle(CAPS_LOCK)
+                                }
                                 keys::KEY_SCROLL_LOCK => self.lock_keys.toggle(SCROLL_LOCK),
                                 keys::KEY_NUM_LOCK => self.lock_keys.toggle(NUM_LOCK),
                                 _ => {}
                             }
                         }
 
+                        if self.lock_keys != old_lock_keys {
+                            lock_keys = Some(self.lock_keys);
+                        }
                         logical = Some(LogicalKeyboardEvent::Command(self.get_modifiers(), key));
                     }
                 }
@@ -204,9 +777,7 @@ impl KeyboardState {
                             // Get symbol from layout
                             let group = self.get_group();
 
-                            if let Some(symbol) = self.layout.get_symbol(group, 1, key) {
-                                logical = Some(LogicalKeyboardEvent::Symbol(symbol));
-                            }
+                            logical = self.get_symbol_event(group, 1, key);
                         }
                     } else {
                         if pressed {
@@ -235,11 +806,65 @@ impl KeyboardState {
             }
         }
 
+        let privileged = if self.privileged_keys_enabled && pressed && !repeat {
+            self.privileged_event(key)
+        } else {
+            None
+        };
+
+        let mut consumed = privileged.is_some();
+        if !consumed && self.unicode_input_enabled && pressed && self.key_modifier(key).is_none() {
+            let start = match logical {
+                Some(LogicalKeyboardEvent::Command(modifiers, keys::KEY_U)) => {
+                    modifiers.side_agnostic() == CTRL | SHIFT && self.unicode_input.is_none()
+                }
+                _ => false,
+            };
+
+            if start {
+                self.unicode_input = Some(String::new());
+                logical = None;
+                consumed = true;
+            } else if self.unicode_input.is_some() {
+                logical = self.actuate_unicode_input(key, logical);
+                consumed = true;
+            }
+        }
+
+        if !consumed && pressed && self.key_modifier(key).is_none() {
+            if let Some(ref mut input_method) = self.input_method {
+                match input_method.key_press(key, logical.as_ref()) {
+                    InputMethodAction::Pass => {}
+                    InputMethodAction::Preedit => {
+                        logical = Some(LogicalKeyboardEvent::Preedit(input_method.preedit()));
+                    }
+                    InputMethodAction::Commit(text) => {
+                        logical = Some(LogicalKeyboardEvent::Commit { text, preedit: input_method.preedit() });
+                    }
+                }
+            }
+        }
+
+        if privileged.is_some() {
+            logical = None;
+        }
+
+        if sticky_event.is_some() {
+            logical = sticky_event;
+        } else if pressed && self.key_modifier(key).is_none() {
+            // Latched modifiers only apply to one key
+            self.latched_modifiers = Modifiers::empty();
+        }
+
         KeyboardEvent {
-            key: key,
-            pressed: pressed,
-            repeat: repeat,
-            logical: logical,
+            key,
+            pressed,
+            repeat,
+            logical,
+            timestamp,
+            held_for,
+            lock_keys,
+            privileged,
         }
     }
 }
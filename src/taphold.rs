@@ -0,0 +1,180 @@
+use std::collections::HashMap;
+use std::mem;
+use std::time::Duration;
+
+use keys::{self, Key};
+
+/// The keys a dual-role key acts as when it is tapped and when it is held
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TapHoldKey {
+    pub tap: Key,
+    pub hold: Key,
+}
+
+impl TapHoldKey {
+    pub fn new(tap: Key, hold: Key) -> TapHoldKey {
+        TapHoldKey { tap, hold }
+    }
+}
+
+/// Decides whether dual-role keys are being tapped or held
+///
+/// The resolver sits in front of KeyboardState. Every key press and release should be passed to
+/// `feed`, and the events it returns passed on to `actuate_key`. While a dual-role key is
+/// undecided, the events that follow it are buffered. Once its role is decided, the tap or hold
+/// key is pressed first and then the buffered events are returned in the order they happened.
+///
+/// A dual-role key is a hold if it is held for longer than `tapping_term`, and a tap if it is
+/// released before then. The `permissive_hold` and `hold_on_other_key_press` options decide it
+/// earlier when other keys are used at the same time.
+///
+/// Timestamps are supplied by the caller in the same way as `KeyRepeater`.
+#[derive(Debug, Clone)]
+pub struct TapHold {
+    /// The dual-role keys
+    pub keys: HashMap<Key, TapHoldKey>,
+
+    /// How long a dual-role key must be held to act as its hold key
+    ///
+    /// default: 200ms
+    pub tapping_term: Duration,
+
+    /// When set to true, a dual-role key is held if another key is pressed and released while it is held
+    ///
+    /// default: false
+    pub permissive_hold: bool,
+
+    /// When set to true, a dual-role key is held as soon as another key is pressed while it is held
+    ///
+    /// default: false
+    pub hold_on_other_key_press: bool,
+
+    /// The dual-role key whose role hasn't been decided yet, and the time it was pressed
+    pending: Option<(Key, Duration)>,
+
+    /// Events that happened while a dual-role key was undecided
+    buffer: Vec<(Key, bool, Duration)>,
+
+    /// The keys that held dual-role keys are acting as, by keycode
+    held: HashMap<u8, Key>,
+}
+
+impl Default for TapHold {
+    fn default() -> TapHold {
+        TapHold::new()
+    }
+}
+
+impl TapHold {
+    /// Creates a new TapHold instance with no dual-role keys
+    pub fn new() -> TapHold {
+        TapHold {
+            keys: HashMap::new(),
+            tapping_term: Duration::from_millis(200),
+            permissive_hold: false,
+            hold_on_other_key_press: false,
+            pending: None,
+            buffer: Vec::new(),
+            held: HashMap::new(),
+        }
+    }
+
+    /// Makes caps lock act as escape when tapped and left control when held
+    pub fn caps_lock_esc_ctrl(&mut self) {
+        self.keys.insert(keys::KEY_CAPS_LOCK, TapHoldKey::new(keys::KEY_ESC, keys::KEY_L_CTRL));
+    }
+
+    /// Makes space act as left shift when held
+    pub fn space_shift(&mut self) {
+        self.keys.insert(keys::KEY_SPACE, TapHoldKey::new(keys::KEY_SPACE, keys::KEY_L_SHIFT));
+    }
+
+    /// Returns the time that `tick` should next be called, or None if no dual-role key is undecided
+    pub fn next_deadline(&self) -> Option<Duration> {
+        self.pending.map(|(_, pressed_at)| pressed_at + self.tapping_term)
+    }
+
+    /// Should be called whenever a key is pressed or released
+    ///
+    /// Returns the key events that should be passed to KeyboardState.
+    pub fn feed(&mut self, key: Key, pressed: bool, now: Duration) -> Vec<(Key, bool)> {
+        let mut events = self.tick(now);
+
+        if let Some((pending, _)) = self.pending {
+            if key == pending {
+                // Hardware repeats don't decide anything
+                if !pressed {
+                    let tap = self.keys.get(&key).map_or(key, |dual| dual.tap);
+                    self.pending = None;
+                    events.push((tap, true));
+                    events.extend(self.replay());
+                    events.push((tap, false));
+                }
+                return events;
+            }
+
+            let released_while_held = !pressed && self.buffer.iter().any(|&(other, pressed, _)| other == key && pressed);
+            self.buffer.push((key, pressed, now));
+
+            if (pressed && self.hold_on_other_key_press) || (released_while_held && self.permissive_hold) {
+                events.extend(self.resolve_hold());
+            }
+            return events;
+        }
+
+        if pressed {
+            if let Some(&hold) = self.held.get(&key.keycode()) {
+                events.push((hold, true));
+            } else if self.keys.contains_key(&key) {
+                self.pending = Some((key, now));
+            } else {
+                events.push((key, true));
+            }
+        } else {
+            match self.held.remove(&key.keycode()) {
+                Some(hold) => events.push((hold, false)),
+                None => events.push((key, false)),
+            }
+        }
+
+        events
+    }
+
+    /// Decides that the undecided dual-role key is held if it has been held for `tapping_term`
+    ///
+    /// Returns the key events that should be passed to KeyboardState.
+    pub fn tick(&mut self, now: Duration) -> Vec<(Key, bool)> {
+        match self.next_deadline() {
+            Some(deadline) if deadline <= now => self.resolve_hold(),
+            _ => Vec::new(),
+        }
+    }
+
+    /// Decides that the undecided dual-role key is held and returns its press and the buffered events
+    fn resolve_hold(&mut self) -> Vec<(Key, bool)> {
+        let mut events = Vec::new();
+
+        if let Some((key, _)) = self.pending.take() {
+            let hold = self.keys.get(&key).map_or(key, |dual| dual.hold);
+            self.held.insert(key.keycode(), hold);
+            events.push((hold, true));
+            events.extend(self.replay());
+        }
+
+        events
+    }
+
+    /// Feeds the buffered events through again, now that the key before them has been decided
+    ///
+    /// This is needed because the buffer may contain another dual-role key.
+    fn replay(&mut self) -> Vec<(Key, bool)> {
+        let buffer = mem::take(&mut self.buffer);
+        let mut events = Vec::new();
+
+        for (key, pressed, time) in buffer {
+            events.extend(self.feed(key, pressed, time));
+        }
+
+        events
+    }
+}
@@ -0,0 +1,513 @@
+use std::char;
+use std::collections::HashMap;
+use std::mem;
+use std::str;
+use std::time::Duration;
+
+use event::{KeyboardEvent, LogicalKeyboardEvent};
+use keys::{self, Key, KeyType};
+use layout::{self, Layout};
+use state::{self, Modifiers};
+
+const ESC: u8 = 0x1B;
+
+/// Whether a decoded key was pressed, repeated or released
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Action {
+    /// The terminal only reports presses, so a release is generated straight after
+    Tap,
+    Press,
+    Repeat,
+    Release,
+}
+
+/// A key that was decoded from the input
+struct Decoded {
+    key: Key,
+    modifiers: Modifiers,
+    symbol: Option<String>,
+    action: Action,
+}
+
+enum Parsed {
+    /// More bytes are needed to decode the sequence
+    Incomplete,
+
+    /// The number of bytes that were decoded, and the key they represent (if any)
+    Done(usize, Option<Decoded>),
+}
+
+/// Converts an xterm modifier parameter (eg. 5 for Ctrl) to modifiers
+fn modifiers_from_param(param: u32) -> Modifiers {
+    let bits = param.saturating_sub(1);
+    let mut modifiers = Modifiers::empty();
+    if bits & 1 != 0 { modifiers.insert(state::SHIFT) }
+    if bits & 2 != 0 { modifiers.insert(state::ALT) }
+    if bits & 4 != 0 { modifiers.insert(state::CTRL) }
+    if bits & 8 != 0 { modifiers.insert(state::SUPER) }
+    modifiers
+}
+
+/// Returns the key for the final character of CSI 1 ; m X and SS3 X sequences
+fn letter_key(letter: u8) -> Option<Key> {
+    match letter {
+        b'A' => Some(keys::KEY_UP),
+        b'B' => Some(keys::KEY_DOWN),
+        b'C' => Some(keys::KEY_RIGHT),
+        b'D' => Some(keys::KEY_LEFT),
+        b'E' => Some(keys::KEY_NUM_5),
+        b'H' => Some(keys::KEY_HOME),
+        b'F' => Some(keys::KEY_END),
+        b'P' => Some(keys::KEY_F1),
+        b'Q' => Some(keys::KEY_F2),
+        b'R' => Some(keys::KEY_F3),
+        b'S' => Some(keys::KEY_F4),
+        _ => None,
+    }
+}
+
+/// Returns the key for the final character of SS3 sequences sent by the numpad in application keypad mode
+fn keypad_key(letter: u8) -> Option<Key> {
+    match letter {
+        b'p' => Some(keys::KEY_NUM_0),
+        b'q' => Some(keys::KEY_NUM_1),
+        b'r' => Some(keys::KEY_NUM_2),
+        b's' => Some(keys::KEY_NUM_3),
+        b't' => Some(keys::KEY_NUM_4),
+        b'u' => Some(keys::KEY_NUM_5),
+        b'v' => Some(keys::KEY_NUM_6),
+        b'w' => Some(keys::KEY_NUM_7),
+        b'x' => Some(keys::KEY_NUM_8),
+        b'y' => Some(keys::KEY_NUM_9),
+        b'n' => Some(keys::KEY_NUM_DECIMAL),
+        b'M' => Some(keys::KEY_NUM_ENTER),
+        b'k' => Some(keys::KEY_NUM_ADD),
+        b'm' => Some(keys::KEY_NUM_SUB),
+        b'j' => Some(keys::KEY_NUM_MUL),
+        b'o' => Some(keys::KEY_NUM_DIV),
+        _ => None,
+    }
+}
+
+/// Returns the key for the number of CSI n ~ sequences
+fn tilde_key(number: u32) -> Option<Key> {
+    match number {
+        1 | 7 => Some(keys::KEY_HOME),
+        2 => Some(keys::KEY_INS),
+        3 => Some(keys::KEY_DEL),
+        4 | 8 => Some(keys::KEY_END),
+        5 => Some(keys::KEY_PG_UP),
+        6 => Some(keys::KEY_PG_DOWN),
+        11 => Some(keys::KEY_F1),
+        12 => Some(keys::KEY_F2),
+        13 => Some(keys::KEY_F3),
+        14 => Some(keys::KEY_F4),
+        15 => Some(keys::KEY_F5),
+        17 => Some(keys::KEY_F6),
+        18 => Some(keys::KEY_F7),
+        19 => Some(keys::KEY_F8),
+        20 => Some(keys::KEY_F9),
+        21 => Some(keys::KEY_F10),
+        23 => Some(keys::KEY_F11),
+        24 => Some(keys::KEY_F12),
+        _ => None,
+    }
+}
+
+/// Returns the key for codes of the kitty keyboard protocol that aren't characters
+fn functional_key(code: u32) -> Option<Key> {
+    match code {
+        9 => Some(keys::KEY_TAB),
+        13 => Some(keys::KEY_ENTER),
+        27 => Some(keys::KEY_ESC),
+        127 => Some(keys::KEY_BACKSPACE),
+        57358 => Some(keys::KEY_CAPS_LOCK),
+        57359 => Some(keys::KEY_SCROLL_LOCK),
+        57360 => Some(keys::KEY_NUM_LOCK),
+        57361 => Some(keys::KEY_PRT_SCR),
+        57362 => Some(keys::KEY_PAUSE),
+        57363 => Some(keys::KEY_MENU),
+        57399 ..= 57408 => keypad_key(b'p' + (code - 57399) as u8),
+        57409 => Some(keys::KEY_NUM_DECIMAL),
+        57410 => Some(keys::KEY_NUM_DIV),
+        57411 => Some(keys::KEY_NUM_MUL),
+        57412 => Some(keys::KEY_NUM_SUB),
+        57413 => Some(keys::KEY_NUM_ADD),
+        57414 => Some(keys::KEY_NUM_ENTER),
+        57441 => Some(keys::KEY_L_SHIFT),
+        57442 => Some(keys::KEY_L_CTRL),
+        57443 => Some(keys::KEY_ALT),
+        57444 => Some(keys::KEY_L_SUPER),
+        57447 => Some(keys::KEY_R_SHIFT),
+        57448 => Some(keys::KEY_R_CTRL),
+        57449 => Some(keys::KEY_ALT_GR),
+        57450 => Some(keys::KEY_R_SUPER),
+        _ => None,
+    }
+}
+
+/// Splits CSI parameters into fields separated by ';', each made of sub-parameters separated by ':'
+fn parse_params(params: &str) -> Vec<Vec<Option<u32>>> {
+    params.split(';')
+        .map(|field| field.split(':').map(|value| value.parse().ok()).collect())
+        .collect()
+}
+
+/// Returns a parameter, or the default if it is missing
+fn param(fields: &[Vec<Option<u32>>], field: usize, sub: usize, default: u32) -> u32 {
+    fields.get(field).and_then(|field| field.get(sub).cloned()).and_then(|value| value).unwrap_or(default)
+}
+
+/// Converts bytes typed in a terminal (eg. read from stdin in raw mode) into keyboard events
+///
+/// Understands plain characters, C0 control characters, Alt as an ESC prefix, xterm CSI and
+/// SS3 sequences (with modifier parameters), modifyOtherKeys and the kitty keyboard protocol
+/// (CSI u, including repeat and release events).
+///
+/// A lone ESC byte could be the Escape key or the start of a sequence that hasn't fully arrived
+/// yet. It is only decoded as the Escape key once `timeout` has passed without more bytes, so
+/// `tick` should be called at `next_deadline`.
+///
+/// Terminals only report key presses (unless the kitty protocol is asked to report event
+/// types), so each press is followed by a release event.
+///
+/// Terminals don't say which side a modifier was on, so Command events only have the
+/// side-agnostic modifier flags (eg. CTRL, never L_CTRL). Use `LogicalKeyboardEvent::side_agnostic`
+/// when comparing them with events from `KeyboardState`.
+///
+/// Timestamps are supplied by the caller in the same way as `KeyRepeater`.
+#[derive(Debug, Clone)]
+pub struct TerminalDecoder {
+    /// How long to wait for the rest of a sequence after an ESC byte
+    ///
+    /// default: 50ms
+    pub timeout: Duration,
+
+    /// Set to true if the program has asked the terminal to report key event types (kitty
+    /// protocol flag 2). Presses are then not followed by a release, as the terminal reports them.
+    ///
+    /// default: false
+    pub report_event_types: bool,
+
+    /// The layout used to find the key and level that type each character
+    layout: Layout,
+    symbol_keys: HashMap<char, (Key, u8)>,
+
+    /// Bytes that haven't been decoded yet, and the time the decoder started waiting for more
+    buffer: Vec<u8>,
+    waiting_since: Option<Duration>,
+}
+
+impl Default for TerminalDecoder {
+    fn default() -> TerminalDecoder {
+        TerminalDecoder::new(layout::us())
+    }
+}
+
+impl TerminalDecoder {
+    /// Creates a new TerminalDecoder, which finds the keys for characters using the layout
+    pub fn new(layout: Layout) -> TerminalDecoder {
+        // When several keys type the same symbol, prefer the lowest level and then the lowest keycode
+        let mut symbol_keys: HashMap<char, (Key, u8)> = HashMap::new();
+        for (&(group, level, key), &symbol) in &layout.key_symbol_map {
+            if group != 0 {
+                continue;
+            }
+            let better = match symbol_keys.get(&symbol) {
+                Some(&(existing, existing_level)) => (level, key.keycode()) < (existing_level, existing.keycode()),
+                None => true,
+            };
+            if better {
+                symbol_keys.insert(symbol, (key, level));
+            }
+        }
+
+        TerminalDecoder {
+            timeout: Duration::from_millis(50),
+            report_event_types: false,
+            layout,
+            symbol_keys,
+            buffer: Vec::new(),
+            waiting_since: None,
+        }
+    }
+
+    /// Returns the time that `tick` should next be called, or None if no bytes are waiting
+    pub fn next_deadline(&self) -> Option<Duration> {
+        self.waiting_since.map(|since| since + self.timeout)
+    }
+
+    /// Decodes the bytes and returns the key events they contain
+    ///
+    /// Incomplete sequences are kept until more bytes arrive or the timeout passes.
+    pub fn feed(&mut self, bytes: &[u8], now: Duration) -> Vec<KeyboardEvent> {
+        self.buffer.extend_from_slice(bytes);
+
+        let mut events = Vec::new();
+        let mut consumed = 0;
+        while consumed < self.buffer.len() {
+            match self.parse(&self.buffer[consumed..]) {
+                Parsed::Incomplete => break,
+                Parsed::Done(length, decoded) => {
+                    consumed += length;
+                    if let Some(decoded) = decoded {
+                        events.extend(self.events(decoded, now));
+                    }
+                }
+            }
+        }
+        self.buffer.drain(..consumed);
+
+        if self.buffer.is_empty() {
+            self.waiting_since = None;
+        } else if consumed > 0 || self.waiting_since.is_none() {
+            self.waiting_since = Some(now);
+        }
+
+        events
+    }
+
+    /// Decodes the bytes that are still waiting if the timeout has passed
+    ///
+    /// A lone ESC becomes the Escape key, and ESC followed by '[' or 'O' becomes Alt with
+    /// that key. Other incomplete sequences are dropped.
+    pub fn tick(&mut self, now: Duration) -> Vec<KeyboardEvent> {
+        match self.next_deadline() {
+            Some(deadline) if deadline <= now => {}
+            _ => return Vec::new(),
+        }
+
+        let buffer = mem::take(&mut self.buffer);
+        self.waiting_since = None;
+
+        let decoded = match buffer.as_slice() {
+            [ESC] => Some(self.decode_control(keys::KEY_ESC, Modifiers::empty())),
+            [ESC, next @ b'['] | [ESC, next @ b'O'] => self.decode_char(*next as char).map(|mut decoded| {
+                decoded.modifiers.insert(state::ALT);
+                decoded
+            }),
+            _ => None,
+        };
+
+        decoded.map_or_else(Vec::new, |decoded| self.events(decoded, now))
+    }
+
+    /// Converts a decoded key into events, with the logical event KeyboardState would give it
+    fn events(&self, decoded: Decoded, now: Duration) -> Vec<KeyboardEvent> {
+        let Decoded { key, modifiers, symbol, action } = decoded;
+
+        let command = modifiers.intersects(state::CTRL | state::ALT | state::SUPER)
+            || key.key_type() == Some(KeyType::Control);
+        let logical = match symbol {
+            Some(_) if action == Action::Release => None,
+            Some(symbol) if !command => {
+                let mut chars = symbol.chars();
+                match (chars.next(), chars.next()) {
+                    (Some(c), None) => Some(LogicalKeyboardEvent::Symbol(c)),
+                    _ => Some(LogicalKeyboardEvent::Text(symbol)),
+                }
+            }
+            _ if action == Action::Release => None,
+            _ => Some(LogicalKeyboardEvent::Command(modifiers, key)),
+        };
+
+        let event = |pressed, repeat, logical| KeyboardEvent {
+            key,
+            pressed,
+            repeat,
+            logical,
+            timestamp: Some(now),
+            held_for: None,
+            lock_keys: None,
+            privileged: None,
+        };
+        match action {
+            Action::Tap if self.report_event_types => vec![event(true, false, logical)],
+            Action::Tap => vec![event(true, false, logical), event(false, false, None)],
+            Action::Press => vec![event(true, false, logical)],
+            Action::Repeat => vec![event(true, true, logical)],
+            Action::Release => vec![event(false, false, None)],
+        }
+    }
+
+    /// Returns a key that doesn't type a symbol
+    fn decode_control(&self, key: Key, modifiers: Modifiers) -> Decoded {
+        Decoded { key, modifiers, symbol: None, action: Action::Tap }
+    }
+
+    /// Returns the key that types the character, with shift if it is on level 1
+    fn decode_char(&self, c: char) -> Option<Decoded> {
+        let (key, level) = self.symbol_keys.get(&c).cloned().unwrap_or((keys::KEY_UNKNOWN, 0));
+        let modifiers = if level == 1 { state::SHIFT } else { Modifiers::empty() };
+
+        Some(Decoded { key, modifiers, symbol: Some(c.to_string()), action: Action::Tap })
+    }
+
+    /// Returns the key for a C0 control character, which is typed by holding Ctrl
+    fn decode_c0(&self, byte: u8) -> Option<Decoded> {
+        let decoded = match byte {
+            b'\r' => self.decode_control(keys::KEY_ENTER, Modifiers::empty()),
+            b'\t' => self.decode_control(keys::KEY_TAB, Modifiers::empty()),
+            0x7F => self.decode_control(keys::KEY_BACKSPACE, Modifiers::empty()),
+            0x08 => self.decode_control(keys::KEY_BACKSPACE, state::CTRL),
+            0x00 => self.decode_control(keys::KEY_SPACE, state::CTRL),
+            _ => {
+                let c = match byte {
+                    0x01 ..= 0x1A => (byte + 0x60) as char,
+                    0x1C => '\\',
+                    0x1D => ']',
+                    0x1E => '^',
+                    0x1F => '_',
+                    _ => return None,
+                };
+                let mut decoded = self.decode_char(c)?;
+                decoded.modifiers.insert(state::CTRL);
+                decoded
+            }
+        };
+
+        Some(decoded)
+    }
+
+    /// Decodes a character or control character that isn't part of an escape sequence
+    fn parse_plain(&self, bytes: &[u8]) -> Parsed {
+        let byte = bytes[0];
+        if byte < 0x20 || byte == 0x7F {
+            return Parsed::Done(1, self.decode_c0(byte));
+        }
+
+        let length = match byte {
+            0x00 ..= 0x7F => 1,
+            0xC0 ..= 0xDF => 2,
+            0xE0 ..= 0xEF => 3,
+            0xF0 ..= 0xF7 => 4,
+            _ => return Parsed::Done(1, None),
+        };
+        if bytes.len() < length {
+            return Parsed::Incomplete;
+        }
+
+        match str::from_utf8(&bytes[..length]) {
+            Ok(text) => Parsed::Done(length, text.chars().next().and_then(|c| self.decode_char(c))),
+            Err(_) => Parsed::Done(1, None),
+        }
+    }
+
+    fn parse(&self, bytes: &[u8]) -> Parsed {
+        if bytes[0] != ESC {
+            return self.parse_plain(bytes);
+        }
+
+        match bytes.get(1) {
+            None => Parsed::Incomplete,
+            Some(&b'[') => self.parse_csi(bytes),
+            Some(&b'O') => self.parse_ss3(bytes),
+            Some(&ESC) => Parsed::Done(2, Some(self.decode_control(keys::KEY_ESC, state::ALT))),
+            Some(_) => match self.parse_plain(&bytes[1..]) {
+                Parsed::Incomplete => Parsed::Incomplete,
+                Parsed::Done(length, decoded) => Parsed::Done(length + 1, decoded.map(|mut decoded| {
+                    decoded.modifiers.insert(state::ALT);
+                    decoded
+                })),
+            },
+        }
+    }
+
+    /// Decodes SS3 sequences (ESC O X), sent by cursor keys, F1-F4 and the numpad in application modes
+    fn parse_ss3(&self, bytes: &[u8]) -> Parsed {
+        // Some terminals put a modifier parameter before the final character (eg. ESC O 5 A)
+        let (modifiers, index) = match bytes.get(2) {
+            None => return Parsed::Incomplete,
+            Some(&digit @ b'1' ..= b'9') => (modifiers_from_param((digit - b'0') as u32), 3),
+            Some(_) => (Modifiers::empty(), 2),
+        };
+        let letter = match bytes.get(index) {
+            Some(&letter) => letter,
+            None => return Parsed::Incomplete,
+        };
+
+        let decoded = if let Some(key) = letter_key(letter) {
+            Some(self.decode_control(key, modifiers))
+        } else {
+            keypad_key(letter).map(|key| {
+                let symbol = self.layout.get_symbol(0, 1, key).or_else(|| self.layout.get_symbol(0, 0, key));
+                Decoded { key, modifiers, symbol: symbol.map(|c| c.to_string()), action: Action::Tap }
+            })
+        };
+
+        Parsed::Done(index + 1, decoded)
+    }
+
+    /// Decodes CSI sequences (ESC [ parameters final)
+    fn parse_csi(&self, bytes: &[u8]) -> Parsed {
+        let end = match bytes[2..].iter().position(|&byte| (0x40..=0x7E).contains(&byte)) {
+            Some(position) => position + 2,
+            None if bytes[2..].iter().all(|&byte| (0x20..=0x3F).contains(&byte)) => return Parsed::Incomplete,
+            None => return Parsed::Done(2, None),
+        };
+
+        let params = String::from_utf8_lossy(&bytes[2..end]);
+        let length = end + 1;
+
+        // Private sequences (eg. responses to queries) aren't keys
+        if params.starts_with(['?', '<', '=', '>']) {
+            return Parsed::Done(length, None);
+        }
+
+        let fields = parse_params(&params);
+        let modifiers = modifiers_from_param(param(&fields, 1, 0, 1));
+        let action = match param(&fields, 1, 1, 0) {
+            2 => Action::Repeat,
+            3 => Action::Release,
+            1 => Action::Press,
+            _ => Action::Tap,
+        };
+
+        let decoded = match bytes[end] {
+            b'u' => self.decode_code(param(&fields, 0, 0, 0), modifiers, fields.get(2)),
+            b'~' if param(&fields, 0, 0, 0) == 27 => {
+                // modifyOtherKeys: CSI 27 ; modifiers ; code ~
+                self.decode_code(param(&fields, 2, 0, 0), modifiers, None)
+            }
+            b'~' => tilde_key(param(&fields, 0, 0, 0)).map(|key| self.decode_control(key, modifiers)),
+            b'Z' => Some(self.decode_control(keys::KEY_TAB, modifiers | state::SHIFT)),
+            letter => letter_key(letter).map(|key| self.decode_control(key, modifiers)),
+        };
+
+        Parsed::Done(length, decoded.map(|mut decoded| {
+            decoded.action = action;
+            decoded
+        }))
+    }
+
+    /// Decodes the key code of the kitty protocol and modifyOtherKeys, which is a character or a functional key
+    fn decode_code(&self, code: u32, modifiers: Modifiers, text: Option<&Vec<Option<u32>>>) -> Option<Decoded> {
+        if let Some(key) = functional_key(code) {
+            let mut decoded = self.decode_control(key, modifiers);
+            if key.key_type() == Some(KeyType::Numpad) {
+                decoded.symbol = self.layout.get_symbol(0, 1, key).map(|c| c.to_string());
+            }
+            return Some(decoded);
+        }
+
+        let mut decoded = self.decode_char(char::from_u32(code)?)?;
+        decoded.modifiers.insert(modifiers);
+
+        // The code is the unshifted character, so find the symbol shift types on that key
+        if modifiers.contains(state::SHIFT) {
+            if let Some(symbol) = self.layout.get_symbol(0, 1, decoded.key) {
+                decoded.symbol = Some(symbol.to_string());
+            }
+        }
+
+        // The kitty protocol can report the text the key typed
+        if let Some(text) = text {
+            let text: String = text.iter().filter_map(|&c| c.and_then(char::from_u32)).collect();
+            if !text.is_empty() {
+                decoded.symbol = Some(text);
+            }
+        }
+
+        Some(decoded)
+    }
+}
@@ -0,0 +1,194 @@
+use event::{KeyboardEvent, LogicalKeyboardEvent};
+use keys::{self, Key};
+use layout::Layout;
+use state::{self, Modifiers};
+use terminal::TerminalModes;
+
+const ESC: u8 = 0x1B;
+
+/// Returns the xterm modifier parameter (eg. 5 for Ctrl), or None if no modifiers are held
+fn modifier_param(modifiers: Modifiers) -> Option<u8> {
+    let mut param = 0;
+    if modifiers.contains(state::SHIFT) { param += 1 }
+    if modifiers.contains(state::ALT) { param += 2 }
+    if modifiers.contains(state::CTRL) { param += 4 }
+    if modifiers.contains(state::SUPER) { param += 8 }
+
+    if param > 0 { Some(param + 1) } else { None }
+}
+
+/// Returns the C0 control character that Ctrl and the symbol types, if there is one
+fn control_char(symbol: char) -> Option<u8> {
+    match symbol {
+        'a' ..= 'z' | 'A' ..= 'Z' => Some(symbol.to_ascii_uppercase() as u8 - b'@'),
+        '@' | ' ' | '2' => Some(0x00),
+        '[' | '3' => Some(0x1B),
+        '\\' | '4' => Some(0x1C),
+        ']' | '5' => Some(0x1D),
+        '^' | '6' => Some(0x1E),
+        '_' | '/' | '7' => Some(0x1F),
+        '?' | '8' => Some(0x7F),
+        _ => None,
+    }
+}
+
+/// Returns the final character and whether the key is sent with SS3, for keys that send
+/// ESC [ 1 ; m X when modified
+fn letter_key(key: Key, modes: &TerminalModes) -> Option<(u8, bool)> {
+    match key {
+        keys::KEY_UP => Some((b'A', modes.cursor_keys_application)),
+        keys::KEY_DOWN => Some((b'B', modes.cursor_keys_application)),
+        keys::KEY_RIGHT => Some((b'C', modes.cursor_keys_application)),
+        keys::KEY_LEFT => Some((b'D', modes.cursor_keys_application)),
+        keys::KEY_HOME => Some((b'H', modes.cursor_keys_application)),
+        keys::KEY_END => Some((b'F', modes.cursor_keys_application)),
+        keys::KEY_F1 => Some((b'P', true)),
+        keys::KEY_F2 => Some((b'Q', true)),
+        keys::KEY_F3 => Some((b'R', true)),
+        keys::KEY_F4 => Some((b'S', true)),
+        _ => None,
+    }
+}
+
+/// Returns the number of keys that send ESC [ n ~
+fn tilde_key(key: Key) -> Option<u8> {
+    match key {
+        keys::KEY_INS => Some(2),
+        keys::KEY_DEL => Some(3),
+        keys::KEY_PG_UP => Some(5),
+        keys::KEY_PG_DOWN => Some(6),
+        keys::KEY_F5 => Some(15),
+        keys::KEY_F6 => Some(17),
+        keys::KEY_F7 => Some(18),
+        keys::KEY_F8 => Some(19),
+        keys::KEY_F9 => Some(20),
+        keys::KEY_F10 => Some(21),
+        keys::KEY_F11 => Some(23),
+        keys::KEY_F12 => Some(24),
+        _ => None,
+    }
+}
+
+/// Returns the final character that numpad keys send with SS3 in application keypad mode
+fn keypad_key(key: Key) -> Option<u8> {
+    match key {
+        keys::KEY_NUM_0 => Some(b'p'),
+        keys::KEY_NUM_1 => Some(b'q'),
+        keys::KEY_NUM_2 => Some(b'r'),
+        keys::KEY_NUM_3 => Some(b's'),
+        keys::KEY_NUM_4 => Some(b't'),
+        keys::KEY_NUM_5 => Some(b'u'),
+        keys::KEY_NUM_6 => Some(b'v'),
+        keys::KEY_NUM_7 => Some(b'w'),
+        keys::KEY_NUM_8 => Some(b'x'),
+        keys::KEY_NUM_9 => Some(b'y'),
+        keys::KEY_NUM_DECIMAL => Some(b'n'),
+        keys::KEY_NUM_ENTER => Some(b'M'),
+        keys::KEY_NUM_ADD => Some(b'k'),
+        keys::KEY_NUM_SUB => Some(b'm'),
+        keys::KEY_NUM_MUL => Some(b'j'),
+        keys::KEY_NUM_DIV => Some(b'o'),
+        _ => None,
+    }
+}
+
+fn push_char(bytes: &mut Vec<u8>, symbol: char) {
+    let mut buffer = [0; 4];
+    bytes.extend_from_slice(symbol.encode_utf8(&mut buffer).as_bytes());
+}
+
+/// Encodes a key that was pressed with modifiers (from a Command event)
+fn encode_command(modifiers: Modifiers, key: Key, layout: &Layout, modes: &TerminalModes) -> Vec<u8> {
+    let param = modifier_param(modifiers);
+    let mut bytes = Vec::new();
+
+    if let Some((letter, ss3)) = letter_key(key, modes) {
+        match param {
+            Some(param) => bytes.extend(format!("\x1B[1;{}", param).bytes()),
+            None if ss3 => bytes.extend(b"\x1BO"),
+            None => bytes.extend(b"\x1B["),
+        }
+        bytes.push(letter);
+        return bytes;
+    }
+
+    if let Some(number) = tilde_key(key) {
+        match param {
+            Some(param) => bytes.extend(format!("\x1B[{};{}~", number, param).bytes()),
+            None => bytes.extend(format!("\x1B[{}~", number).bytes()),
+        }
+        return bytes;
+    }
+
+    // Super is left to the window manager for the remaining keys
+    if modifiers.contains(state::SUPER) {
+        return bytes;
+    }
+
+    if modifiers.contains(state::ALT) {
+        bytes.push(ESC);
+    }
+
+    let ctrl = modifiers.contains(state::CTRL);
+    match key {
+        keys::KEY_ESC => bytes.push(ESC),
+        keys::KEY_ENTER | keys::KEY_NUM_ENTER => bytes.push(b'\r'),
+        keys::KEY_TAB if modifiers.contains(state::SHIFT) => bytes.extend(b"\x1B[Z"),
+        keys::KEY_TAB => bytes.push(b'\t'),
+        keys::KEY_BACKSPACE => bytes.push(if modes.backspace_sends_delete != ctrl { 0x7F } else { 0x08 }),
+        _ => {
+            let level = if modifiers.contains(state::SHIFT) { 1 } else { 0 };
+            let symbol = layout.get_symbol(0, level, key).or_else(|| layout.get_symbol(0, 0, key));
+
+            match symbol {
+                Some(symbol) if ctrl => match control_char(symbol) {
+                    Some(control) => bytes.push(control),
+                    None => push_char(&mut bytes, symbol),
+                },
+                Some(symbol) => push_char(&mut bytes, symbol),
+                None => bytes.clear(),
+            }
+        }
+    }
+
+    bytes
+}
+
+/// Returns the bytes that a terminal should send to the program when the event happens
+///
+/// Commands are encoded as xterm would: Ctrl and a letter types a C0 control character, Alt
+/// prefixes the key with ESC, and cursor, editing and function keys send escape sequences
+/// with a modifier parameter (eg. ESC [ 1 ; 5 A for Ctrl+Up). The layout is used to find the
+/// symbols of keys in Command events.
+///
+/// Releases and events that don't type anything (such as lock key changes) encode to nothing.
+pub fn encode(event: &KeyboardEvent, layout: &Layout, modes: &TerminalModes) -> Vec<u8> {
+    if !event.pressed {
+        return Vec::new();
+    }
+
+    // In application keypad mode, the numpad sends SS3 sequences while it is typing symbols
+    if modes.keypad_application {
+        if let Some(letter) = keypad_key(event.key) {
+            let typing = match event.logical {
+                Some(LogicalKeyboardEvent::Symbol(_)) => true,
+                Some(LogicalKeyboardEvent::Command(_, key)) => key == event.key,
+                _ => false,
+            };
+            if typing {
+                return vec![ESC, b'O', letter];
+            }
+        }
+    }
+
+    match event.logical {
+        Some(LogicalKeyboardEvent::Symbol(symbol)) => {
+            let mut bytes = Vec::new();
+            push_char(&mut bytes, symbol);
+            bytes
+        }
+        Some(LogicalKeyboardEvent::Text(ref text)) | Some(LogicalKeyboardEvent::Commit { ref text, .. }) => text.as_bytes().to_vec(),
+        Some(LogicalKeyboardEvent::Command(modifiers, key)) => encode_command(modifiers, key, layout, modes),
+        _ => Vec::new(),
+    }
+}
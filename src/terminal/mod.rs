@@ -0,0 +1,34 @@
+/// The modes set by the program running in the terminal that change the bytes keys send
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TerminalModes {
+    /// Cursor keys send SS3 sequences (eg. ESC O A) instead of CSI sequences (DECCKM)
+    ///
+    /// default: false
+    pub cursor_keys_application: bool,
+
+    /// Numpad keys send SS3 sequences instead of their symbols (DECKPAM)
+    ///
+    /// default: false
+    pub keypad_application: bool,
+
+    /// Backspace sends DEL (0x7F) instead of BS (0x08). Ctrl+Backspace sends the other one
+    ///
+    /// default: true
+    pub backspace_sends_delete: bool,
+}
+
+impl Default for TerminalModes {
+    fn default() -> TerminalModes {
+        TerminalModes {
+            cursor_keys_application: false,
+            keypad_application: false,
+            backspace_sends_delete: true,
+        }
+    }
+}
+
+mod encode;
+mod decode;
+
+pub use self::encode::encode;
+pub use self::decode::TerminalDecoder;
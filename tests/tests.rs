@@ -1,40 +1,55 @@
 extern crate orbkb;
 
+use std::time::Duration;
+
+use orbkb::accessx::AccessFilter;
+use orbkb::devices::{DeviceId, KeyboardDevices};
+use orbkb::geometry::{Geometry, Size, Standard};
+use orbkb::hotkeys::{Chord, HotkeyError, HotkeyMatch, HotkeyRegistry};
+use orbkb::ime::{Preedit, RomajiInputMethod, TableInputMethod};
 use orbkb::keys;
-use orbkb::event::KeyboardAction;
-use orbkb::layout::GB;
-use orbkb::state::KeyboardState;
+use orbkb::remap::{self, Remap};
+use orbkb::render;
+use orbkb::repeat::KeyRepeater;
+use orbkb::scancode;
+use orbkb::event::{LogicalKeyboardEvent, PrivilegedEvent};
+use orbkb::layers::{Layer, LayerAction, LayerStack};
+use orbkb::layout;
+use orbkb::mousekeys::{MouseKeys, PointerButton, PointerEvent};
+use orbkb::state::{self, CapsLockMode, KeyboardState};
+use orbkb::taphold::TapHold;
+use orbkb::terminal::{self, TerminalDecoder, TerminalModes};
 
 #[test]
 fn test_simple() {
-    let mut kb_state = KeyboardState::new(&GB);
+    let mut kb_state = KeyboardState::new(layout::gb());
 
     // Press 'a' key, should emit 'a'
     let result = kb_state.actuate_key(keys::KEY_A, true);
-    assert_eq!(result.action, Some(KeyboardAction::Symbol('a')));
+    assert_eq!(result.logical, Some(LogicalKeyboardEvent::Symbol('a')));
     kb_state.actuate_key(keys::KEY_A, false);
 
     // Press '1' key, should emit '1'
     let result = kb_state.actuate_key(keys::KEY_1, true);
-    assert_eq!(result.action, Some(KeyboardAction::Symbol('1')));
+    assert_eq!(result.logical, Some(LogicalKeyboardEvent::Symbol('1')));
     kb_state.actuate_key(keys::KEY_1, false);
 }
 
 #[test]
 fn test_shift_modifier() {
-    let mut kb_state = KeyboardState::new(&GB);
+    let mut kb_state = KeyboardState::new(layout::gb());
 
     // Press the shift key
     kb_state.actuate_key(keys::KEY_L_SHIFT, true);
 
     // Press 'a' key, should emit 'A'
     let result = kb_state.actuate_key(keys::KEY_A, true);
-    assert_eq!(result.action, Some(KeyboardAction::Symbol('A')));
+    assert_eq!(result.logical, Some(LogicalKeyboardEvent::Symbol('A')));
     kb_state.actuate_key(keys::KEY_A, false);
 
     // Press '1' should emit '!'
     let result = kb_state.actuate_key(keys::KEY_1, true);
-    assert_eq!(result.action, Some(KeyboardAction::Symbol('!')));
+    assert_eq!(result.logical, Some(LogicalKeyboardEvent::Symbol('!')));
     kb_state.actuate_key(keys::KEY_1, false);
 
     // Release shift key
@@ -42,44 +57,44 @@ fn test_shift_modifier() {
 
     // Press 'a' key, should now emit 'a'
     let result = kb_state.actuate_key(keys::KEY_A, true);
-    assert_eq!(result.action, Some(KeyboardAction::Symbol('a')));
+    assert_eq!(result.logical, Some(LogicalKeyboardEvent::Symbol('a')));
     kb_state.actuate_key(keys::KEY_A, false);
 }
 
 #[test]
 fn test_alt_gr_modifier() {
-    let mut kb_state = KeyboardState::new(&GB);
+    let mut kb_state = KeyboardState::new(layout::gb());
 
     // Press the alt gr key
     kb_state.actuate_key(keys::KEY_ALT_GR, true);
 
     // Press '`' key, should emit '|'
     let result = kb_state.actuate_key(keys::KEY_BACKTICK, true);
-    assert_eq!(result.action, Some(KeyboardAction::Symbol('|')));
+    assert_eq!(result.logical, Some(LogicalKeyboardEvent::Symbol('|')));
     kb_state.actuate_key(keys::KEY_BACKTICK, false);
 }
 
 #[test]
 fn test_caps_lock() {
-    let mut kb_state = KeyboardState::new(&GB);
+    let mut kb_state = KeyboardState::new(layout::gb());
 
-    assert_eq!(kb_state.caps_lock(), false);
+    assert!(!kb_state.caps_lock());
 
     // Press and release the caps lock key
     kb_state.actuate_key(keys::KEY_CAPS_LOCK, true);
     kb_state.actuate_key(keys::KEY_CAPS_LOCK, false);
 
-    assert_eq!(kb_state.caps_lock(), true);
+    assert!(kb_state.caps_lock());
 
     // Press 'a' key, should emit 'A'
     let result = kb_state.actuate_key(keys::KEY_A, true);
-    assert_eq!(result.action, Some(KeyboardAction::Symbol('A')));
+    assert_eq!(result.logical, Some(LogicalKeyboardEvent::Symbol('A')));
     kb_state.actuate_key(keys::KEY_A, false);
 
     // Press '1' key, should emit '1'
     // Note: numerals and punctuation are unaffected by caps lock
     let result = kb_state.actuate_key(keys::KEY_1, true);
-    assert_eq!(result.action, Some(KeyboardAction::Symbol('1')));
+    assert_eq!(result.logical, Some(LogicalKeyboardEvent::Symbol('1')));
     kb_state.actuate_key(keys::KEY_1, false);
 
     // Press the shift key
@@ -88,12 +103,12 @@ fn test_caps_lock() {
     // Press 'a' key, should emit 'a'
     // Note: The shift key negates the caps lock
     let result = kb_state.actuate_key(keys::KEY_A, true);
-    assert_eq!(result.action, Some(KeyboardAction::Symbol('a')));
+    assert_eq!(result.logical, Some(LogicalKeyboardEvent::Symbol('a')));
     kb_state.actuate_key(keys::KEY_A, false);
 
     // Press '1' key, should emit '!'
     let result = kb_state.actuate_key(keys::KEY_1, true);
-    assert_eq!(result.action, Some(KeyboardAction::Symbol('!')));
+    assert_eq!(result.logical, Some(LogicalKeyboardEvent::Symbol('!')));
     kb_state.actuate_key(keys::KEY_1, false);
 
     // Release the shift key
@@ -103,25 +118,25 @@ fn test_caps_lock() {
     kb_state.actuate_key(keys::KEY_CAPS_LOCK, true);
     kb_state.actuate_key(keys::KEY_CAPS_LOCK, false);
 
-    assert_eq!(kb_state.caps_lock(), false);
+    assert!(!kb_state.caps_lock());
 
     // Press 'a' key, should emit 'a'
     let result = kb_state.actuate_key(keys::KEY_A, true);
-    assert_eq!(result.action, Some(KeyboardAction::Symbol('a')));
+    assert_eq!(result.logical, Some(LogicalKeyboardEvent::Symbol('a')));
     kb_state.actuate_key(keys::KEY_A, false);
 }
 
 #[test]
 fn test_num_lock() {
-    let mut kb_state = KeyboardState::new(&GB);
+    let mut kb_state = KeyboardState::new(layout::gb());
 
-    assert_eq!(kb_state.num_lock(), false);
+    assert!(!kb_state.num_lock());
 
     // Press and release the num lock key
     kb_state.actuate_key(keys::KEY_NUM_LOCK, true);
     kb_state.actuate_key(keys::KEY_NUM_LOCK, false);
 
-    assert_eq!(kb_state.num_lock(), true);
+    assert!(kb_state.num_lock());
 
     // TODO tests
 
@@ -129,52 +144,1406 @@ fn test_num_lock() {
     kb_state.actuate_key(keys::KEY_NUM_LOCK, true);
     kb_state.actuate_key(keys::KEY_NUM_LOCK, false);
 
-    assert_eq!(kb_state.num_lock(), false);
+    assert!(!kb_state.num_lock());
 }
 
 #[test]
 fn test_scroll_lock() {
-    let mut kb_state = KeyboardState::new(&GB);
+    let mut kb_state = KeyboardState::new(layout::gb());
 
-    assert_eq!(kb_state.scroll_lock(), false);
+    assert!(!kb_state.scroll_lock());
 
     // Press and release the scroll lock key
     kb_state.actuate_key(keys::KEY_SCROLL_LOCK, true);
     kb_state.actuate_key(keys::KEY_SCROLL_LOCK, false);
 
-    assert_eq!(kb_state.scroll_lock(), true);
+    assert!(kb_state.scroll_lock());
 
     // Press and release the scroll lock key again
     kb_state.actuate_key(keys::KEY_SCROLL_LOCK, true);
     kb_state.actuate_key(keys::KEY_SCROLL_LOCK, false);
 
-    assert_eq!(kb_state.scroll_lock(), false);
+    assert!(!kb_state.scroll_lock());
 }
 
 #[test]
 fn test_disable_lock_keys() {
-    let mut kb_state = KeyboardState::new(&GB);
+    let mut kb_state = KeyboardState::new(layout::gb());
     kb_state.lock_keys_enabled = false;
 
-    assert_eq!(kb_state.caps_lock(), false);
-    assert_eq!(kb_state.num_lock(), false);
-    assert_eq!(kb_state.scroll_lock(), false);
+    assert!(!kb_state.caps_lock());
+    assert!(!kb_state.num_lock());
+    assert!(!kb_state.scroll_lock());
 
     // Press and release the caps lock key
     kb_state.actuate_key(keys::KEY_CAPS_LOCK, true);
     kb_state.actuate_key(keys::KEY_CAPS_LOCK, false);
 
-    assert_eq!(kb_state.caps_lock(), false);
+    assert!(!kb_state.caps_lock());
 
     // Press and release the num lock key
     kb_state.actuate_key(keys::KEY_NUM_LOCK, true);
     kb_state.actuate_key(keys::KEY_NUM_LOCK, false);
 
-    assert_eq!(kb_state.num_lock(), false);
+    assert!(!kb_state.num_lock());
 
     // Press and release the scroll lock key
     kb_state.actuate_key(keys::KEY_SCROLL_LOCK, true);
     kb_state.actuate_key(keys::KEY_SCROLL_LOCK, false);
 
-    assert_eq!(kb_state.scroll_lock(), false);
+    assert!(!kb_state.scroll_lock());
+}
+
+#[test]
+fn test_layout_registry_lookup() {
+    let registry = layout::LayoutRegistry::builtin();
+
+    // Look up by name
+    let (info, gb) = registry.lookup("gb").unwrap();
+    assert_eq!(info.locale, "en-GB");
+    assert_eq!(*gb, layout::gb());
+
+    // Look up by locale (case and separator are ignored)
+    let (info, _) = registry.lookup("en_us").unwrap();
+    assert_eq!(info.id, "us");
+
+    // Unknown layouts aren't found by lookup
+    assert!(registry.lookup("de(nodeadkeys)").is_none());
+}
+
+#[test]
+fn test_layout_registry_register() {
+    let mut registry = layout::LayoutRegistry::builtin();
+
+    let info = layout::LayoutInfo::new("gb", "English (UK, extended)", "en-GB", "Latn").with_variant("extd");
+    assert_eq!(info.full_id(), "gb(extd)");
+    registry.register(info, layout::gb());
+
+    let (info, _) = registry.lookup("gb(extd)").unwrap();
+    assert_eq!(info.name, "English (UK, extended)");
+    assert_eq!(registry.layouts().len(), 3);
+
+    // Registering the same id and variant again replaces the layout
+    registry.register(layout::LayoutInfo::new("gb", "English (UK)", "en-GB", "Latn").with_variant("extd"), layout::gb());
+    assert_eq!(registry.layouts().len(), 3);
+}
+
+#[test]
+fn test_layout_registry_resolve() {
+    let mut registry = layout::LayoutRegistry::builtin();
+
+    // Missing variants fall back to the default variant
+    let (info, _) = registry.resolve("gb(dvorak)").unwrap();
+    assert_eq!(info.full_id(), "gb");
+
+    // Missing locales fall back to another layout for the same language
+    let (info, _) = registry.resolve("en-AU").unwrap();
+    assert_eq!(info.id, "us");
+
+    // Anything else uses the fallback layout
+    let (info, _) = registry.resolve("de(nodeadkeys)").unwrap();
+    assert_eq!(info.id, "us");
+
+    assert!(registry.set_fallback("gb"));
+    assert!(!registry.set_fallback("fr"));
+    let (info, _) = registry.resolve("de(nodeadkeys)").unwrap();
+    assert_eq!(info.id, "gb");
+
+    assert!(layout::LayoutRegistry::new().resolve("us").is_none());
+}
+
+#[test]
+fn test_caps_lock_per_key_behaviour() {
+    // Make ';' behave like a letter (like 'ö' on a German keyboard) and '1' behave like a
+    // shift lock (like the number row on a French keyboard)
+    let mut layout = layout::gb();
+    layout.key_caps_map.insert(keys::KEY_COLON, layout::CapsBehaviour::Alphabetic);
+    layout.key_caps_map.insert(keys::KEY_1, layout::CapsBehaviour::ShiftLock);
+    layout.key_caps_map.insert(keys::KEY_A, layout::CapsBehaviour::Unaffected);
+    let mut kb_state = KeyboardState::new(layout);
+
+    // Press and release the caps lock key
+    kb_state.actuate_key(keys::KEY_CAPS_LOCK, true);
+    kb_state.actuate_key(keys::KEY_CAPS_LOCK, false);
+
+    // Press ';' key, should emit ':'
+    let result = kb_state.actuate_key(keys::KEY_COLON, true);
+    assert_eq!(result.logical, Some(LogicalKeyboardEvent::Symbol(':')));
+    kb_state.actuate_key(keys::KEY_COLON, false);
+
+    // Press '1' key, should emit '!'
+    let result = kb_state.actuate_key(keys::KEY_1, true);
+    assert_eq!(result.logical, Some(LogicalKeyboardEvent::Symbol('!')));
+    kb_state.actuate_key(keys::KEY_1, false);
+
+    // Press 'a' key, should emit 'a'
+    let result = kb_state.actuate_key(keys::KEY_A, true);
+    assert_eq!(result.logical, Some(LogicalKeyboardEvent::Symbol('a')));
+    kb_state.actuate_key(keys::KEY_A, false);
+
+    // Press the shift key
+    kb_state.actuate_key(keys::KEY_L_SHIFT, true);
+
+    // Press ';' key, should emit ';'
+    // Note: The shift key negates the caps lock on alphabetic keys
+    let result = kb_state.actuate_key(keys::KEY_COLON, true);
+    assert_eq!(result.logical, Some(LogicalKeyboardEvent::Symbol(';')));
+    kb_state.actuate_key(keys::KEY_COLON, false);
+
+    // Press '1' key, should still emit '!'
+    let result = kb_state.actuate_key(keys::KEY_1, true);
+    assert_eq!(result.logical, Some(LogicalKeyboardEvent::Symbol('!')));
+    kb_state.actuate_key(keys::KEY_1, false);
+}
+
+#[test]
+fn test_caps_lock_mode_shift_lock() {
+    let mut kb_state = KeyboardState::new(layout::gb());
+    kb_state.caps_lock_mode = CapsLockMode::ShiftLock;
+
+    // Press and release the caps lock key
+    kb_state.actuate_key(keys::KEY_CAPS_LOCK, true);
+    kb_state.actuate_key(keys::KEY_CAPS_LOCK, false);
+
+    assert!(kb_state.caps_lock());
+
+    // Press '1' key, should emit '!'
+    let result = kb_state.actuate_key(keys::KEY_1, true);
+    assert_eq!(result.logical, Some(LogicalKeyboardEvent::Symbol('!')));
+    kb_state.actuate_key(keys::KEY_1, false);
+
+    // Press the shift key and 'a', should still emit 'A'
+    kb_state.actuate_key(keys::KEY_L_SHIFT, true);
+    let result = kb_state.actuate_key(keys::KEY_A, true);
+    assert_eq!(result.logical, Some(LogicalKeyboardEvent::Symbol('A')));
+    kb_state.actuate_key(keys::KEY_A, false);
+    kb_state.actuate_key(keys::KEY_L_SHIFT, false);
+}
+
+#[test]
+fn test_caps_lock_mode_shift() {
+    let mut kb_state = KeyboardState::new(layout::gb());
+    kb_state.caps_lock_mode = CapsLockMode::Shift;
+
+    // Press the caps lock key, it should not toggle
+    kb_state.actuate_key(keys::KEY_CAPS_LOCK, true);
+    assert!(!kb_state.caps_lock());
+    assert!(kb_state.shift());
+
+    // Press '1' key, should emit '!'
+    let result = kb_state.actuate_key(keys::KEY_1, true);
+    assert_eq!(result.logical, Some(LogicalKeyboardEvent::Symbol('!')));
+    kb_state.actuate_key(keys::KEY_1, false);
+
+    // Release the caps lock key
+    kb_state.actuate_key(keys::KEY_CAPS_LOCK, false);
+    assert!(!kb_state.caps_lock());
+
+    // Press 'a' key, should emit 'a'
+    let result = kb_state.actuate_key(keys::KEY_A, true);
+    assert_eq!(result.logical, Some(LogicalKeyboardEvent::Symbol('a')));
+    kb_state.actuate_key(keys::KEY_A, false);
+}
+
+#[test]
+fn test_multi_character_symbols() {
+    // Put the Dutch "ij" ligature on AltGr+i
+    let mut layout = layout::gb();
+    layout.key_string_map.insert((1, 0, keys::KEY_I), "ij".to_string());
+    layout.key_string_map.insert((1, 1, keys::KEY_I), "IJ".to_string());
+    let mut kb_state = KeyboardState::new(layout);
+
+    // Press the alt gr key
+    kb_state.actuate_key(keys::KEY_ALT_GR, true);
+
+    // Press 'i' key, should emit "ij"
+    let result = kb_state.actuate_key(keys::KEY_I, true);
+    assert_eq!(result.logical, Some(LogicalKeyboardEvent::Text("ij".to_string())));
+    kb_state.actuate_key(keys::KEY_I, false);
+
+    // Press shift and 'i' key, should emit "IJ"
+    kb_state.actuate_key(keys::KEY_L_SHIFT, true);
+    let result = kb_state.actuate_key(keys::KEY_I, true);
+    assert_eq!(result.logical, Some(LogicalKeyboardEvent::Text("IJ".to_string())));
+    kb_state.actuate_key(keys::KEY_I, false);
+    kb_state.actuate_key(keys::KEY_L_SHIFT, false);
+
+    // Release the alt gr key
+    kb_state.actuate_key(keys::KEY_ALT_GR, false);
+
+    // Press 'i' key, should emit 'i'
+    let result = kb_state.actuate_key(keys::KEY_I, true);
+    assert_eq!(result.logical, Some(LogicalKeyboardEvent::Symbol('i')));
+    kb_state.actuate_key(keys::KEY_I, false);
+}
+
+#[test]
+fn test_render_text() {
+    let text = render::render_text(&layout::gb(), &Geometry::new(Standard::Iso, Size::SixtyPercent));
+    let lines: Vec<&str> = text.lines().collect();
+
+    // Five rows of keys, each taking three lines, plus the bottom border
+    assert_eq!(lines.len(), 16);
+    assert!(lines.iter().all(|line| line.chars().count() == 61));
+
+    // The '3' key shows '£' on level 1 and '3' on level 0
+    assert!(lines[1].starts_with("|¬  |!  |"));
+    assert!(lines[1].contains("|£  |"));
+    assert!(lines[2].contains("|3  |"));
+
+    // The '`' key shows '|' on the AltGr group
+    assert!(lines[2].starts_with("|` ||"));
+
+    // Keys without symbols are labelled with their name
+    assert!(lines[7].starts_with("|Caps  |A  |"));
+
+    // ANSI keyboards don't have the key beside the left shift
+    let text = render::render_text(&layout::us(), &Geometry::new(Standard::Ansi, Size::SixtyPercent));
+    assert!(text.lines().nth(10).unwrap().starts_with("|Shift   |Z  |"));
+}
+
+#[test]
+fn test_render_svg() {
+    let svg = render::render_svg(&layout::gb(), &Geometry::new(Standard::Iso, Size::SixtyPercent));
+
+    assert!(svg.starts_with("<svg "));
+    assert!(svg.ends_with("</svg>\n"));
+
+    // Symbols are escaped
+    assert!(svg.contains(">&lt;</text>"));
+    assert!(svg.contains(">&amp;</text>"));
+
+    // The ISO enter key is drawn as a single outline
+    assert_eq!(svg.matches("<path ").count(), 1);
+    assert_eq!(render::render_svg(&layout::us(), &Geometry::new(Standard::Ansi, Size::SixtyPercent)).matches("<path ").count(), 0);
+}
+
+#[test]
+fn test_geometry_sizes() {
+    assert_eq!(Geometry::ansi_104().keys.len(), 104);
+    assert_eq!(Geometry::iso_105().keys.len(), 105 + 1);  // The ISO enter key has two parts
+    assert_eq!(Geometry::jis_109().keys.len(), 109 + 1);
+    assert_eq!(Geometry::new(Standard::Ansi, Size::Tenkeyless).keys.len(), 87);
+    assert_eq!(Geometry::new(Standard::Ansi, Size::SixtyPercent).keys.len(), 61);
+
+    let geometry = Geometry::ansi_104();
+    assert_eq!(geometry.width(), 22.5);
+    assert_eq!(geometry.height(), 6.5);
+    assert_eq!(geometry.rows(), 6);
+
+    let position = geometry.position(keys::KEY_SPACE).unwrap();
+    assert_eq!((position.row, position.x, position.y, position.width), (5, 3.75, 5.5, 6.25));
+
+    assert!(!geometry.has_key(keys::KEY_B_SLASH));
+    assert!(Geometry::iso_105().has_key(keys::KEY_B_SLASH));
+    assert!(Geometry::jis_109().has_key(keys::KEY_YEN));
+    assert!(!Geometry::new(Standard::Ansi, Size::Tenkeyless).has_key(keys::KEY_NUM_5));
+}
+
+#[test]
+fn test_geometry_neighbours() {
+    let geometry = Geometry::ansi_104();
+
+    assert_eq!(geometry.key_below(keys::KEY_Q), Some(keys::KEY_A));
+    assert_eq!(geometry.key_above(keys::KEY_2), Some(keys::KEY_F1));
+    assert_eq!(geometry.key_above(keys::KEY_1), None);
+    assert_eq!(geometry.key_left_of(keys::KEY_S), Some(keys::KEY_A));
+    assert_eq!(geometry.key_right_of(keys::KEY_S), Some(keys::KEY_D));
+    assert_eq!(geometry.key_left_of(keys::KEY_A), Some(keys::KEY_CAPS_LOCK));
+    assert_eq!(geometry.key_left_of(keys::KEY_CAPS_LOCK), None);
+    assert_eq!(geometry.key_below(keys::KEY_UP), Some(keys::KEY_DOWN));
+    assert_eq!(geometry.key_left_of(keys::KEY_NUM_ADD), Some(keys::KEY_NUM_6));
+    assert_eq!(geometry.key_below(keys::KEY_NUM_9), Some(keys::KEY_NUM_6));
+    assert_eq!(geometry.key_right_of(keys::KEY_NUM_6), Some(keys::KEY_NUM_ADD));
+}
+
+#[test]
+fn test_geometry_ansi_iso_hash_key() {
+    // On ANSI keyboards, KEY_HASH sits above the enter key
+    let ansi = Geometry::ansi_104();
+    assert_eq!(ansi.key_right_of(keys::KEY_CL_BRACKET), Some(keys::KEY_HASH));
+    assert_eq!(ansi.key_below(keys::KEY_HASH), Some(keys::KEY_ENTER));
+    assert_eq!(ansi.key_right_of(keys::KEY_QUOTE), Some(keys::KEY_ENTER));
+
+    // On ISO keyboards, it sits to the left of the enter key
+    let iso = Geometry::iso_105();
+    assert_eq!(iso.key_right_of(keys::KEY_CL_BRACKET), Some(keys::KEY_ENTER));
+    assert_eq!(iso.key_right_of(keys::KEY_QUOTE), Some(keys::KEY_HASH));
+    assert_eq!(iso.key_right_of(keys::KEY_HASH), Some(keys::KEY_ENTER));
+    assert_eq!(iso.key_below(keys::KEY_ENTER), Some(keys::KEY_R_SHIFT));
+
+    // Scancode 0x29 is the key to the left of '1' on both
+    assert_eq!(scancode::scancode_to_key(false, 0x29), Some(keys::KEY_BACKTICK));
+    assert_eq!(iso.key_right_of(keys::KEY_BACKTICK), Some(keys::KEY_1));
+    assert_eq!(scancode::scancode_to_key(false, 0x2B), Some(keys::KEY_HASH));
+}
+
+#[test]
+fn test_layout_diff() {
+    assert!(layout::diff(&layout::gb(), &layout::gb()).is_empty());
+
+    let mut new = layout::gb();
+    new.has_alt_gr_key = false;
+    new.key_symbol_map.insert((0, 1, keys::KEY_3), '#');
+    new.key_symbol_map.remove(&(1, 0, keys::KEY_BACKTICK));
+    new.key_string_map.insert((1, 0, keys::KEY_I), "ij".to_string());
+    new.key_caps_map.insert(keys::KEY_COLON, layout::CapsBehaviour::Alphabetic);
+    new.key_caps_map.insert(keys::KEY_A, layout::CapsBehaviour::Alphabetic);  // Same as the default
+
+    let diff = layout::diff(&layout::gb(), &new);
+    assert_eq!(diff.has_alt_gr_key, Some((true, false)));
+    assert_eq!(diff.caps_behaviours, vec![
+        layout::CapsChange { key: keys::KEY_COLON, old: layout::CapsBehaviour::Unaffected, new: layout::CapsBehaviour::Alphabetic },
+    ]);
+    assert_eq!(diff.symbols, vec![
+        layout::SymbolChange { group: 0, level: 1, key: keys::KEY_3, change: layout::Change::Changed("£".to_string(), "#".to_string()) },
+        layout::SymbolChange { group: 1, level: 0, key: keys::KEY_I, change: layout::Change::Added("ij".to_string()) },
+        layout::SymbolChange { group: 1, level: 0, key: keys::KEY_BACKTICK, change: layout::Change::Removed("|".to_string()) },
+    ]);
+
+    assert_eq!(diff.to_string(), "\
+~ has_alt_gr_key: true -> false
+~ caps lock on ;: Unaffected -> Alphabetic
+~ 3 (group 0, level 1): \"£\" -> \"#\"
++ I (group 1, level 0): \"ij\"
+- ` (group 1, level 0): \"|\"
+");
+
+    // Change the layout details, should report them before the symbols
+    let info = layout::LayoutInfo::new("gb", "English (UK)", "en-GB", "Latn");
+    let new_info = info.clone().with_variant("extd");
+    assert_eq!(layout::diff_with_info((&info, &layout::gb()), (&new_info, &layout::gb())).to_string(), "+ variant: \"extd\"\n");
+}
+
+#[test]
+fn test_layout_file() {
+    let registry = layout::LayoutRegistry::builtin();
+    let (info, gb) = registry.lookup("gb").unwrap();
+
+    // Write the layout and read it back, should be the same
+    let text = layout::to_text(info, gb);
+    assert!(text.starts_with("id gb\nname English (UK)\nlocale en-GB\nscript Latn\nalt_gr true\n"));
+    let (parsed_info, parsed) = layout::parse(&text).unwrap();
+    assert_eq!(&parsed_info, info);
+    assert!(layout::diff(gb, &parsed).is_empty());
+
+    // Change the details and a symbol, should report both
+    let text = text.replace("name English (UK)", "name English (United Kingdom)")
+        .replace("symbol 3 0 1 \"£\"", "symbol 3 0 1 \"#\"");
+    let (new_info, new) = layout::parse(&text).unwrap();
+    assert_eq!(layout::diff_with_info((info, gb), (&new_info, &new)).to_string(), "\
+~ name: \"English (UK)\" -> \"English (United Kingdom)\"
+~ 3 (group 0, level 1): \"£\" -> \"#\"
+");
+
+    let error = layout::parse("id gb\nsymbol NOT_A_KEY 0 0 \"a\"").unwrap_err();
+    assert_eq!(error.to_string(), "line 2: unknown key 'NOT_A_KEY'");
+}
+
+#[test]
+fn test_key_repeater() {
+    let ms = Duration::from_millis;
+    let mut kb_state = KeyboardState::new(layout::gb());
+    let mut repeater = KeyRepeater::new(ms(500), ms(100));
+
+    // Press 'a' key
+    let result = kb_state.actuate_key(keys::KEY_A, true);
+    repeater.key_event(&result, ms(1000));
+    assert_eq!(repeater.next_deadline(), Some(ms(1500)));
+
+    // Nothing should repeat before the delay
+    assert_eq!(repeater.tick(&mut kb_state, ms(1499)), vec![]);
+
+    // After the delay it should repeat every interval
+    let events = repeater.tick(&mut kb_state, ms(1500));
+    assert_eq!(events.len(), 1);
+    assert_eq!(events[0].key, keys::KEY_A);
+    assert!(events[0].pressed);
+    assert!(events[0].repeat);
+    assert_eq!(events[0].logical, Some(LogicalKeyboardEvent::Symbol('a')));
+    assert_eq!(events[0].timestamp, Some(ms(1500)));
+    assert_eq!(repeater.next_deadline(), Some(ms(1600)));
+
+    // Pressing shift doesn't interrupt the repeat, but changes the symbol
+    let result = kb_state.actuate_key(keys::KEY_L_SHIFT, true);
+    repeater.key_event(&result, ms(1550));
+
+    // Missed repeats are returned as one, and the next deadline stays in step with the interval
+    let events = repeater.tick(&mut kb_state, ms(1820));
+    assert_eq!(events.len(), 1);
+    assert_eq!(events[0].logical, Some(LogicalKeyboardEvent::Symbol('A')));
+    assert_eq!(repeater.next_deadline(), Some(ms(1900)));
+
+    // A very late tick (eg. after a suspend) should still only return one repeat
+    assert_eq!(repeater.tick(&mut kb_state, ms(3_600_000)).len(), 1);
+    assert_eq!(repeater.next_deadline(), Some(ms(3_600_100)));
+
+    // Releasing the key stops the repeat
+    let result = kb_state.actuate_key(keys::KEY_A, false);
+    repeater.key_event(&result, ms(1850));
+    assert_eq!(repeater.next_deadline(), None);
+    assert_eq!(repeater.tick(&mut kb_state, ms(5000)), vec![]);
+}
+
+#[test]
+fn test_key_repeater_last_key() {
+    let ms = Duration::from_millis;
+    let mut kb_state = KeyboardState::new(layout::gb());
+    let mut repeater = KeyRepeater::new(ms(500), ms(100));
+
+    // Press 'a' then 'b', only 'b' should repeat
+    let result = kb_state.actuate_key(keys::KEY_A, true);
+    repeater.key_event(&result, ms(0));
+    let result = kb_state.actuate_key(keys::KEY_B, true);
+    repeater.key_event(&result, ms(200));
+    assert_eq!(repeater.repeating_key(), Some(keys::KEY_B));
+
+    // Releasing 'a' doesn't stop 'b' from repeating
+    let result = kb_state.actuate_key(keys::KEY_A, false);
+    repeater.key_event(&result, ms(300));
+    let events = repeater.tick(&mut kb_state, ms(700));
+    assert_eq!(events.len(), 1);
+    assert_eq!(events[0].logical, Some(LogicalKeyboardEvent::Symbol('b')));
+
+    // Lock keys and modifiers never repeat
+    let result = kb_state.actuate_key(keys::KEY_B, false);
+    repeater.key_event(&result, ms(800));
+    let result = kb_state.actuate_key(keys::KEY_CAPS_LOCK, true);
+    repeater.key_event(&result, ms(900));
+    let result = kb_state.actuate_key(keys::KEY_L_CTRL, true);
+    repeater.key_event(&result, ms(900));
+    assert_eq!(repeater.next_deadline(), None);
+}
+
+#[test]
+fn test_sticky_keys() {
+    let mut kb_state = KeyboardState::new(layout::gb());
+    kb_state.sticky_keys_enabled = true;
+
+    // Tap shift, should latch it
+    kb_state.actuate_key(keys::KEY_L_SHIFT, true);
+    let result = kb_state.actuate_key(keys::KEY_L_SHIFT, false);
+    assert_eq!(result.logical.map(|logical| logical.side_agnostic()), Some(LogicalKeyboardEvent::StickyModifiers { latched: state::SHIFT, locked: state::Modifiers::empty() }));
+    assert!(kb_state.shift());
+
+    // Press 'a' key, should emit 'A' and release the latch
+    let result = kb_state.actuate_key(keys::KEY_A, true);
+    assert_eq!(result.logical, Some(LogicalKeyboardEvent::Symbol('A')));
+    kb_state.actuate_key(keys::KEY_A, false);
+    let result = kb_state.actuate_key(keys::KEY_A, true);
+    assert_eq!(result.logical, Some(LogicalKeyboardEvent::Symbol('a')));
+    kb_state.actuate_key(keys::KEY_A, false);
+
+    // Tap ctrl, then press 'c' key, should emit a command
+    kb_state.actuate_key(keys::KEY_L_CTRL, true);
+    kb_state.actuate_key(keys::KEY_L_CTRL, false);
+    let result = kb_state.actuate_key(keys::KEY_C, true);
+    assert_eq!(result.logical.map(|logical| logical.side_agnostic()), Some(LogicalKeyboardEvent::Command(state::CTRL, keys::KEY_C)));
+    kb_state.actuate_key(keys::KEY_C, false);
+    assert!(!kb_state.ctrl());
+
+    // Hold shift while pressing 'a' key, shouldn't latch shift
+    kb_state.actuate_key(keys::KEY_L_SHIFT, true);
+    kb_state.actuate_key(keys::KEY_A, true);
+    kb_state.actuate_key(keys::KEY_A, false);
+    let result = kb_state.actuate_key(keys::KEY_L_SHIFT, false);
+    assert_eq!(result.logical, None);
+    assert!(!kb_state.shift());
+}
+
+#[test]
+fn test_sticky_keys_lock() {
+    let mut kb_state = KeyboardState::new(layout::gb());
+    kb_state.sticky_keys_enabled = true;
+
+    // Tap shift twice, should lock it
+    for _ in 0..2 {
+        kb_state.actuate_key(keys::KEY_R_SHIFT, true);
+        kb_state.actuate_key(keys::KEY_R_SHIFT, false);
+    }
+    assert_eq!(kb_state.locked_modifiers.side_agnostic(), state::SHIFT);
+
+    for _ in 0..2 {
+        let result = kb_state.actuate_key(keys::KEY_A, true);
+        assert_eq!(result.logical, Some(LogicalKeyboardEvent::Symbol('A')));
+        kb_state.actuate_key(keys::KEY_A, false);
+    }
+
+    // Tap shift again, should unlock it
+    kb_state.actuate_key(keys::KEY_R_SHIFT, true);
+    kb_state.actuate_key(keys::KEY_R_SHIFT, false);
+    let result = kb_state.actuate_key(keys::KEY_A, true);
+    assert_eq!(result.logical, Some(LogicalKeyboardEvent::Symbol('a')));
+}
+
+#[test]
+fn test_sticky_keys_shortcut() {
+    let mut kb_state = KeyboardState::new(layout::gb());
+    kb_state.sticky_keys_shortcut = true;
+
+    // Press shift five times, should turn on sticky keys
+    for i in 0..5 {
+        let result = kb_state.actuate_key(keys::KEY_L_SHIFT, true);
+        kb_state.actuate_key(keys::KEY_L_SHIFT, false);
+        if i == 4 {
+            assert_eq!(result.logical, Some(LogicalKeyboardEvent::StickyKeys(true)));
+        }
+    }
+    assert!(kb_state.sticky_keys_enabled);
+    assert!(!kb_state.shift());
+}
+
+#[test]
+fn test_slow_keys() {
+    let mut filter = AccessFilter::new();
+    filter.slow_keys = Some(Duration::from_millis(300));
+
+    // Tap 'a' key, should be ignored
+    assert_eq!(filter.feed(keys::KEY_A, true, Duration::from_millis(0)), vec![]);
+    assert_eq!(filter.next_deadline(), Some(Duration::from_millis(300)));
+    assert_eq!(filter.feed(keys::KEY_A, false, Duration::from_millis(100)), vec![]);
+    assert_eq!(filter.tick(Duration::from_millis(300)), vec![]);
+
+    // Hold 'a' key, should be accepted after the delay
+    filter.feed(keys::KEY_A, true, Duration::from_millis(1000));
+    assert_eq!(filter.tick(Duration::from_millis(1200)), vec![]);
+    assert_eq!(filter.tick(Duration::from_millis(1300)), vec![(keys::KEY_A, true)]);
+    assert_eq!(filter.feed(keys::KEY_A, false, Duration::from_millis(1400)), vec![(keys::KEY_A, false)]);
+}
+
+#[test]
+fn test_bounce_keys() {
+    let mut filter = AccessFilter::new();
+    filter.bounce_keys = Some(Duration::from_millis(200));
+
+    assert_eq!(filter.feed(keys::KEY_A, true, Duration::from_millis(0)), vec![(keys::KEY_A, true)]);
+    assert_eq!(filter.feed(keys::KEY_A, false, Duration::from_millis(50)), vec![(keys::KEY_A, false)]);
+
+    // Press 'a' key again too soon, should be ignored along with its release
+    assert_eq!(filter.feed(keys::KEY_A, true, Duration::from_millis(100)), vec![]);
+    assert_eq!(filter.feed(keys::KEY_A, false, Duration::from_millis(150)), vec![]);
+
+    // Other keys aren't affected
+    assert_eq!(filter.feed(keys::KEY_B, true, Duration::from_millis(160)), vec![(keys::KEY_B, true)]);
+
+    // Press 'a' key after the delay, should be accepted
+    assert_eq!(filter.feed(keys::KEY_A, true, Duration::from_millis(300)), vec![(keys::KEY_A, true)]);
+}
+
+#[test]
+fn test_mouse_keys() {
+    let mut mouse_keys = MouseKeys::new();
+    let ms = Duration::from_millis;
+    let none = state::Modifiers::empty();
+
+    // Numpad keys should be passed on until mouse keys is turned on
+    assert_eq!(mouse_keys.feed(keys::KEY_NUM_6, true, none, ms(0)), None);
+    assert_eq!(mouse_keys.feed(keys::KEY_NUM_6, false, none, ms(0)), None);
+    assert_eq!(mouse_keys.feed(keys::KEY_NUM_LOCK, true, state::SHIFT | state::ALT, ms(0)), Some(vec![]));
+    assert_eq!(mouse_keys.feed(keys::KEY_NUM_LOCK, false, state::SHIFT | state::ALT, ms(0)), Some(vec![]));
+    assert!(mouse_keys.enabled);
+
+    // Hold '6' key, should move right and accelerate
+    assert_eq!(mouse_keys.feed(keys::KEY_NUM_6, true, none, ms(1000)), Some(vec![PointerEvent::Motion { dx: 1, dy: 0 }]));
+    assert_eq!(mouse_keys.tick(ms(1100)), vec![]);
+    assert_eq!(mouse_keys.tick(ms(1160)), vec![PointerEvent::Motion { dx: 1, dy: 0 }]);
+    assert_eq!(mouse_keys.tick(ms(1180)), vec![PointerEvent::Motion { dx: 1, dy: 0 }]);
+    assert_eq!(mouse_keys.tick(ms(1200)), vec![PointerEvent::Motion { dx: 2, dy: 0 }]);
+
+    // Tick late, should move as far as the missed movements would have in one event
+    assert_eq!(mouse_keys.tick(ms(2160)), vec![PointerEvent::Motion { dx: 532, dy: 0 }]);
+
+    // Hold '8' key as well, should move diagonally
+    mouse_keys.feed(keys::KEY_NUM_8, true, none, ms(2170));
+    assert_eq!(mouse_keys.tick(ms(2180)), vec![PointerEvent::Motion { dx: 20, dy: -20 }]);
+    mouse_keys.feed(keys::KEY_NUM_8, false, none, ms(2190));
+    mouse_keys.feed(keys::KEY_NUM_6, false, none, ms(2190));
+    assert_eq!(mouse_keys.next_deadline(), None);
+
+    // Select the right button and click it
+    assert_eq!(mouse_keys.feed(keys::KEY_NUM_SUB, true, none, ms(3000)), Some(vec![]));
+    assert_eq!(mouse_keys.feed(keys::KEY_NUM_5, true, none, ms(3000)), Some(vec![
+        PointerEvent::Button { button: PointerButton::Right, pressed: true },
+        PointerEvent::Button { button: PointerButton::Right, pressed: false },
+    ]));
+    assert_eq!(mouse_keys.feed(keys::KEY_NUM_5, false, none, ms(3000)), Some(vec![]));
+
+    // Other keys should be passed on
+    assert_eq!(mouse_keys.feed(keys::KEY_A, true, none, ms(3000)), None);
+
+    // A late tick should fold the missed movements into one, at full speed once accelerated
+    mouse_keys.feed(keys::KEY_NUM_2, true, none, ms(4000));
+    mouse_keys.tick(ms(5160));
+    assert_eq!(mouse_keys.tick(ms(5180)), vec![PointerEvent::Motion { dx: 0, dy: 20 }]);
+    assert_eq!(mouse_keys.tick(ms(3_605_180)), vec![PointerEvent::Motion { dx: 0, dy: 20 * 180_000 }]);
+    assert_eq!(mouse_keys.next_deadline(), Some(ms(3_605_200)));
+    mouse_keys.feed(keys::KEY_NUM_2, false, none, ms(3_605_190));
+
+    // A max speed below the starting speed shouldn't move the pointer backwards
+    mouse_keys.max_speed = 0;
+    mouse_keys.feed(keys::KEY_NUM_6, true, none, ms(4_000_000));
+    assert_eq!(mouse_keys.tick(ms(4_000_160)), vec![PointerEvent::Motion { dx: 1, dy: 0 }]);
+    assert_eq!(mouse_keys.tick(ms(4_001_160)), vec![PointerEvent::Motion { dx: 50, dy: 0 }]);
+}
+
+#[test]
+fn test_remap() {
+    let mut kb_state = KeyboardState::new(layout::gb());
+    kb_state.remap = remap::caps_lock_to_ctrl();
+
+    // Press caps lock key, should act as ctrl
+    let events = kb_state.actuate_physical_key(keys::KEY_CAPS_LOCK, true);
+    assert_eq!(events.len(), 1);
+    assert_eq!(events[0].key, keys::KEY_L_CTRL);
+    assert!(kb_state.ctrl());
+    assert!(!kb_state.caps_lock());
+
+    // Change the remap while caps lock is held, the release should still release ctrl
+    kb_state.remap = Remap::new();
+    let events = kb_state.actuate_physical_key(keys::KEY_CAPS_LOCK, false);
+    assert_eq!(events[0].key, keys::KEY_L_CTRL);
+    assert!(!kb_state.ctrl());
+    assert!(!kb_state.key_pressed(keys::KEY_CAPS_LOCK));
+
+    // Press insert key, should do nothing
+    kb_state.remap = remap::disable_insert();
+    assert_eq!(kb_state.actuate_physical_key(keys::KEY_INS, true), vec![]);
+    assert!(!kb_state.key_pressed(keys::KEY_INS));
+
+    // Press F1, should press ctrl then c
+    kb_state.remap.map_many(keys::KEY_F1, &[keys::KEY_L_CTRL, keys::KEY_C]);
+    let events = kb_state.actuate_physical_key(keys::KEY_F1, true);
+    assert_eq!(events[1].logical.as_ref().map(|logical| logical.side_agnostic()), Some(LogicalKeyboardEvent::Command(state::CTRL, keys::KEY_C)));
+    let events = kb_state.actuate_physical_key(keys::KEY_F1, false);
+    assert_eq!(events.iter().map(|event| event.key).collect::<Vec<_>>(), vec![keys::KEY_C, keys::KEY_L_CTRL]);
+}
+
+#[test]
+fn test_remap_actuate_key() {
+    let mut kb_state = KeyboardState::new(layout::gb());
+    kb_state.remap = remap::swap_esc_caps_lock();
+
+    // Press caps lock key through actuate_key, should act as escape
+    let result = kb_state.actuate_key(keys::KEY_CAPS_LOCK, true);
+    assert_eq!(result.key, keys::KEY_ESC);
+    assert!(kb_state.key_pressed(keys::KEY_ESC));
+    assert!(!kb_state.caps_lock());
+    assert_eq!(kb_state.actuate_key(keys::KEY_CAPS_LOCK, false).key, keys::KEY_ESC);
+
+    // Press a disabled key, should do nothing
+    kb_state.remap = remap::disable_insert();
+    let result = kb_state.actuate_key(keys::KEY_INS, true);
+    assert_eq!((result.key, result.logical), (keys::KEY_INS, None));
+    assert!(!kb_state.key_pressed(keys::KEY_INS));
+
+    // Hold caps lock (as ctrl) and left ctrl, releasing one should leave ctrl held
+    kb_state.remap = remap::caps_lock_to_ctrl();
+    kb_state.actuate_key(keys::KEY_CAPS_LOCK, true);
+    kb_state.actuate_key(keys::KEY_L_CTRL, true);
+    assert_eq!(kb_state.actuate_physical_key(keys::KEY_CAPS_LOCK, false), vec![]);
+    assert!(kb_state.ctrl());
+    assert_eq!(kb_state.actuate_key(keys::KEY_C, true).logical, Some(LogicalKeyboardEvent::Command(state::CTRL | state::L_CTRL, keys::KEY_C)));
+    kb_state.actuate_key(keys::KEY_C, false);
+    kb_state.actuate_key(keys::KEY_L_CTRL, false);
+    assert!(!kb_state.ctrl());
+
+    // Alt and super should swap, but AltGr should still be AltGr
+    kb_state.remap = remap::swap_alt_super();
+    assert_eq!(kb_state.actuate_key(keys::KEY_ALT, true).key, keys::KEY_L_SUPER);
+    assert_eq!(kb_state.actuate_key(keys::KEY_ALT_GR, true).key, keys::KEY_ALT_GR);
+}
+
+#[test]
+fn test_tap_hold() {
+    let mut tap_hold = TapHold::new();
+    tap_hold.caps_lock_esc_ctrl();
+    let ms = Duration::from_millis;
+
+    // Tap caps lock key, should emit escape
+    assert_eq!(tap_hold.feed(keys::KEY_CAPS_LOCK, true, ms(0)), vec![]);
+    assert_eq!(tap_hold.next_deadline(), Some(ms(200)));
+    assert_eq!(tap_hold.feed(keys::KEY_CAPS_LOCK, false, ms(100)), vec![(keys::KEY_ESC, true), (keys::KEY_ESC, false)]);
+
+    // Hold caps lock key, should emit ctrl once the tapping term has passed
+    tap_hold.feed(keys::KEY_CAPS_LOCK, true, ms(1000));
+    assert_eq!(tap_hold.feed(keys::KEY_C, true, ms(1100)), vec![]);
+    assert_eq!(tap_hold.tick(ms(1200)), vec![(keys::KEY_L_CTRL, true), (keys::KEY_C, true)]);
+    assert_eq!(tap_hold.feed(keys::KEY_C, false, ms(1300)), vec![(keys::KEY_C, false)]);
+    assert_eq!(tap_hold.feed(keys::KEY_CAPS_LOCK, false, ms(1400)), vec![(keys::KEY_L_CTRL, false)]);
+
+    // Roll from caps lock to 'a' key, should emit escape then 'a'
+    tap_hold.feed(keys::KEY_CAPS_LOCK, true, ms(2000));
+    tap_hold.feed(keys::KEY_A, true, ms(2050));
+    assert_eq!(tap_hold.feed(keys::KEY_CAPS_LOCK, false, ms(2100)), vec![(keys::KEY_ESC, true), (keys::KEY_A, true), (keys::KEY_ESC, false)]);
+    assert_eq!(tap_hold.feed(keys::KEY_A, false, ms(2150)), vec![(keys::KEY_A, false)]);
+}
+
+#[test]
+fn test_tap_hold_options() {
+    let mut tap_hold = TapHold::new();
+    tap_hold.space_shift();
+    tap_hold.permissive_hold = true;
+    let ms = Duration::from_millis;
+
+    // Press and release 'a' key within space, should emit shift+a
+    tap_hold.feed(keys::KEY_SPACE, true, ms(0));
+    assert_eq!(tap_hold.feed(keys::KEY_A, true, ms(50)), vec![]);
+    assert_eq!(tap_hold.feed(keys::KEY_A, false, ms(100)), vec![(keys::KEY_L_SHIFT, true), (keys::KEY_A, true), (keys::KEY_A, false)]);
+    assert_eq!(tap_hold.feed(keys::KEY_SPACE, false, ms(150)), vec![(keys::KEY_L_SHIFT, false)]);
+
+    // Press another key while space is held, should emit shift straight away
+    tap_hold.permissive_hold = false;
+    tap_hold.hold_on_other_key_press = true;
+    tap_hold.feed(keys::KEY_SPACE, true, ms(1000));
+    assert_eq!(tap_hold.feed(keys::KEY_A, true, ms(1050)), vec![(keys::KEY_L_SHIFT, true), (keys::KEY_A, true)]);
+}
+
+#[test]
+fn test_layers() {
+    let mut stack = LayerStack::new();
+    stack.layers[0].key_map.insert(keys::KEY_R_CTRL, LayerAction::Momentary(1));
+    stack.layers[0].key_map.insert(keys::KEY_MENU, LayerAction::OneShot(1));
+
+    let mut fn_layer = Layer::new("fn");
+    fn_layer.key_map.insert(keys::KEY_1, LayerAction::Key(keys::KEY_F1));
+    fn_layer.key_map.insert(keys::KEY_ESC, LayerAction::Key(keys::KEY_BACKTICK));
+    fn_layer.key_map.insert(keys::KEY_CAPS_LOCK, LayerAction::None);
+    let fn_layer = stack.add_layer(fn_layer);
+
+    // Press '1' key, should emit '1'
+    assert_eq!(stack.feed(keys::KEY_1, true), vec![(keys::KEY_1, true)]);
+    assert_eq!(stack.feed(keys::KEY_1, false), vec![(keys::KEY_1, false)]);
+
+    // Hold Fn and press '1' key, should emit F1. Keys missing from the layer are transparent
+    assert_eq!(stack.feed(keys::KEY_R_CTRL, true), vec![]);
+    assert!(stack.is_active(fn_layer));
+    assert_eq!(stack.feed(keys::KEY_1, true), vec![(keys::KEY_F1, true)]);
+    assert_eq!(stack.feed(keys::KEY_A, true), vec![(keys::KEY_A, true)]);
+    assert_eq!(stack.feed(keys::KEY_CAPS_LOCK, true), vec![]);
+
+    // Release Fn before '1' key, release should still be F1
+    assert_eq!(stack.feed(keys::KEY_R_CTRL, false), vec![]);
+    assert!(!stack.is_active(fn_layer));
+    assert_eq!(stack.feed(keys::KEY_1, false), vec![(keys::KEY_F1, false)]);
+    assert_eq!(stack.feed(keys::KEY_CAPS_LOCK, false), vec![]);
+
+    // One-shot Fn, should only apply to the next key
+    stack.feed(keys::KEY_MENU, true);
+    stack.feed(keys::KEY_MENU, false);
+    assert_eq!(stack.feed(keys::KEY_ESC, true), vec![(keys::KEY_BACKTICK, true)]);
+    assert_eq!(stack.feed(keys::KEY_ESC, false), vec![(keys::KEY_BACKTICK, false)]);
+    assert_eq!(stack.feed(keys::KEY_ESC, true), vec![(keys::KEY_ESC, true)]);
+}
+
+#[test]
+fn test_layer_toggle() {
+    let mut stack = LayerStack::new();
+    let layer = stack.add_layer(Layer::new("numpad"));
+    stack.layers[layer].key_map.insert(keys::KEY_J, LayerAction::Key(keys::KEY_NUM_1));
+    stack.layers[0].key_map.insert(keys::KEY_SCROLL_LOCK, LayerAction::Toggle(layer));
+
+    stack.feed(keys::KEY_SCROLL_LOCK, true);
+    stack.feed(keys::KEY_SCROLL_LOCK, false);
+    assert_eq!(stack.active_layers(), vec![0, layer]);
+    assert_eq!(stack.feed(keys::KEY_J, true), vec![(keys::KEY_NUM_1, true)]);
+
+    stack.feed(keys::KEY_SCROLL_LOCK, true);
+    stack.feed(keys::KEY_SCROLL_LOCK, false);
+    assert_eq!(stack.active_layers(), vec![0]);
+}
+
+#[test]
+fn test_hotkeys() {
+    let mut registry = HotkeyRegistry::new();
+    let terminal = registry.register("Ctrl+Alt+T").unwrap();
+    let save = registry.register("Ctrl+X Ctrl+S").unwrap();
+    let ms = Duration::from_millis;
+
+    assert_eq!(registry.register("ctrl+alt+t"), Err(HotkeyError::Conflict(terminal)));
+    assert_eq!(registry.register("Ctrl+X"), Err(HotkeyError::Ambiguous(save)));
+    assert_eq!(registry.register("Ctrl+Foo"), Err(HotkeyError::Parse("Ctrl+Foo".to_string())));
+    assert_eq!(keys::Key::from_name("Page Up"), Some(keys::KEY_PG_UP));
+
+    // Key names and modifiers can contain spaces, and '+' can be the key
+    assert_eq!(HotkeyRegistry::parse("Ctrl+Caps Lock"), Ok(vec![Chord::new(state::CTRL, keys::KEY_CAPS_LOCK)]));
+    assert_eq!(HotkeyRegistry::parse("Ctrl+X Ctrl+Page Up"),
+               Ok(vec![Chord::new(state::CTRL, keys::KEY_X), Chord::new(state::CTRL, keys::KEY_PG_UP)]));
+    assert_eq!(HotkeyRegistry::parse("Alt Gr+E"), Ok(vec![Chord::new(state::ALT_GR, keys::KEY_E)]));
+    assert_eq!(HotkeyRegistry::parse("Ctrl++"), Ok(vec![Chord::new(state::CTRL, keys::KEY_NUM_ADD)]));
+    assert_eq!(HotkeyRegistry::parse("Ctrl+Num + +"),
+               Ok(vec![Chord::new(state::CTRL, keys::KEY_NUM_ADD), Chord::new(state::Modifiers::empty(), keys::KEY_NUM_ADD)]));
+    assert_eq!(Chord::parse(&Chord::new(state::CTRL, keys::KEY_NUM_ADD).to_string()), Ok(Chord::new(state::CTRL, keys::KEY_NUM_ADD)));
+    assert_eq!(HotkeyRegistry::parse("Ctrl+"), Err(HotkeyError::Parse("Ctrl+".to_string())));
+
+    // Press Ctrl+Alt+T through KeyboardState, should match
+    let mut kb_state = KeyboardState::new(layout::us());
+    kb_state.actuate_key(keys::KEY_L_CTRL, true);
+    kb_state.actuate_key(keys::KEY_ALT, true);
+    let event = kb_state.actuate_key(keys::KEY_T, true);
+    assert_eq!(registry.feed_event(&event, ms(0)), HotkeyMatch::Matched(terminal));
+
+    // Press Ctrl+X then Ctrl+S, should match the sequence
+    assert_eq!(registry.feed(state::CTRL, keys::KEY_X, ms(1000)), HotkeyMatch::Partial);
+    assert_eq!(registry.feed(state::CTRL, keys::KEY_L_CTRL, ms(1100)), HotkeyMatch::Partial);
+    assert_eq!(registry.feed(state::CTRL, keys::KEY_S, ms(1200)), HotkeyMatch::Matched(save));
+
+    // Wait too long between the chords, shouldn't match
+    assert_eq!(registry.feed(state::CTRL, keys::KEY_X, ms(2000)), HotkeyMatch::Partial);
+    assert_eq!(registry.feed(state::CTRL, keys::KEY_S, ms(3500)), HotkeyMatch::None);
+
+    // Break a sequence with the start of another hotkey
+    assert_eq!(registry.feed(state::CTRL, keys::KEY_X, ms(4000)), HotkeyMatch::Partial);
+    assert_eq!(registry.feed(state::CTRL, keys::KEY_X, ms(4100)), HotkeyMatch::Partial);
+    assert_eq!(registry.feed(state::CTRL, keys::KEY_S, ms(4200)), HotkeyMatch::Matched(save));
+}
+
+#[test]
+fn test_modifier_sides() {
+    let mut kb_state = KeyboardState::new(layout::us());
+
+    // Press right ctrl and 'c' key, command should say which ctrl was used
+    kb_state.actuate_key(keys::KEY_R_CTRL, true);
+    assert!(kb_state.ctrl());
+    assert!(kb_state.r_ctrl() && !kb_state.l_ctrl());
+    let result = kb_state.actuate_key(keys::KEY_C, true);
+    assert_eq!(result.logical, Some(LogicalKeyboardEvent::Command(state::CTRL | state::R_CTRL, keys::KEY_C)));
+    assert_eq!(kb_state.get_modifiers().side_agnostic(), state::CTRL);
+    assert_eq!(result.logical.map(|logical| logical.side_agnostic()), Some(LogicalKeyboardEvent::Command(state::CTRL, keys::KEY_C)));
+    assert_eq!(kb_state.get_modifiers().to_bits(), 2);
+
+    // Without an AltGr key, it acts as the right alt key
+    kb_state.actuate_key(keys::KEY_ALT_GR, true);
+    assert!(kb_state.alt() && kb_state.r_alt());
+    assert_eq!(kb_state.get_modifiers(), state::CTRL | state::R_CTRL | state::ALT | state::R_ALT);
+
+    // Hotkeys can require a side
+    let mut registry = HotkeyRegistry::new();
+    let host_key = registry.register("RCtrl+F1").unwrap();
+    assert_eq!(registry.register("Ctrl+F1"), Err(HotkeyError::Conflict(host_key)));
+    assert!(registry.register("LCtrl+F1").is_ok());
+    assert_eq!(registry.feed(state::CTRL | state::R_CTRL, keys::KEY_F1, Duration::from_millis(0)), HotkeyMatch::Matched(host_key));
+}
+
+#[test]
+fn test_lock_key_events() {
+    let mut kb_state = KeyboardState::new(layout::gb());
+
+    // Press caps lock key, should emit a command along with the new lock state
+    let result = kb_state.actuate_key(keys::KEY_CAPS_LOCK, true);
+    assert!(matches!(result.logical, Some(LogicalKeyboardEvent::Command(_, keys::KEY_CAPS_LOCK))));
+    assert_eq!(result.lock_keys, Some(state::CAPS_LOCK));
+
+    // Hold it, repeats shouldn't toggle it again
+    let result = kb_state.actuate_key(keys::KEY_CAPS_LOCK, true);
+    assert!(result.repeat);
+    assert_eq!(result.lock_keys, None);
+    assert!(kb_state.caps_lock());
+    let result = kb_state.actuate_key(keys::KEY_CAPS_LOCK, false);
+    assert_eq!(result.lock_keys, None);
+    assert!(kb_state.caps_lock());
+
+    let result = kb_state.actuate_key(keys::KEY_NUM_LOCK, true);
+    assert!(matches!(result.logical, Some(LogicalKeyboardEvent::Command(_, keys::KEY_NUM_LOCK))));
+    assert_eq!(result.lock_keys, Some(state::CAPS_LOCK | state::NUM_LOCK));
+    assert_eq!(kb_state.lock_keys.to_ps2_leds(), 0b110);
+    assert_eq!(kb_state.lock_keys.to_hid_leds(), 0b011);
+
+    // Set the state from the keyboard LEDs
+    kb_state.set_lock_keys(state::LockKeyState::from_hid_leds(0b100));
+    assert!(kb_state.scroll_lock() && !kb_state.caps_lock() && !kb_state.num_lock());
+    assert_eq!(state::LockKeyState::from_ps2_leds(0b001), state::SCROLL_LOCK);
+
+    // With lock keys disabled, should only emit a command
+    kb_state.lock_keys_enabled = false;
+    let result = kb_state.actuate_key(keys::KEY_F1, true);
+    assert!(matches!(result.logical, Some(LogicalKeyboardEvent::Command(_, keys::KEY_F1))));
+    let result = kb_state.actuate_key(keys::KEY_SCROLL_LOCK, true);
+    assert!(matches!(result.logical, Some(LogicalKeyboardEvent::Command(_, keys::KEY_SCROLL_LOCK))));
+    assert_eq!(result.lock_keys, None);
+}
+
+#[test]
+fn test_release_all() {
+    let mut kb_state = KeyboardState::new(layout::gb());
+    kb_state.actuate_key(keys::KEY_L_SHIFT, true);
+    kb_state.actuate_key(keys::KEY_A, true);
+    kb_state.actuate_key(keys::KEY_CAPS_LOCK, true);
+
+    // Should release every key in keycode order, leaving the caps lock on
+    let events = kb_state.release_all();
+    assert_eq!(events.iter().map(|event| event.key).collect::<Vec<_>>(), vec![keys::KEY_A, keys::KEY_L_SHIFT, keys::KEY_CAPS_LOCK]);
+    assert!(events.iter().all(|event| !event.pressed));
+    assert!(!kb_state.shift());
+    assert!(kb_state.caps_lock());
+    assert_eq!(kb_state.release_all(), vec![]);
+}
+
+#[test]
+fn test_sync_pressed() {
+    let mut kb_state = KeyboardState::new(layout::gb());
+    kb_state.actuate_key(keys::KEY_L_SHIFT, true);
+    kb_state.actuate_key(keys::KEY_A, true);
+
+    // Shift was released and ctrl and num lock were pressed while the state wasn't listening
+    let events = kb_state.sync_pressed(&[keys::KEY_NUM_LOCK, keys::KEY_A, keys::KEY_L_CTRL]);
+    let changes: Vec<_> = events.iter().map(|event| (event.key, event.pressed)).collect();
+    assert_eq!(changes, vec![(keys::KEY_L_SHIFT, false), (keys::KEY_L_CTRL, true), (keys::KEY_NUM_LOCK, true)]);
+    assert!(events.iter().all(|event| event.logical.is_none()));
+    assert!(kb_state.ctrl() && !kb_state.shift());
+    assert!(!kb_state.num_lock());
+
+    // Nothing changed
+    assert_eq!(kb_state.sync_pressed(&[keys::KEY_A, keys::KEY_L_CTRL, keys::KEY_NUM_LOCK]), vec![]);
+
+    // Insert was pressed while disabled and released while the state wasn't listening, it
+    // shouldn't stay disabled once the remap is removed
+    kb_state.remap = remap::disable_insert();
+    kb_state.actuate_physical_key(keys::KEY_INS, true);
+    kb_state.sync_pressed(&[]);
+    kb_state.remap = Remap::new();
+    let events = kb_state.actuate_physical_key(keys::KEY_INS, true);
+    assert_eq!(events.iter().map(|event| event.key).collect::<Vec<_>>(), vec![keys::KEY_INS]);
+
+    // Hold caps lock as ctrl across a sync, should keep ctrl held until caps lock is released
+    let mut kb_state = KeyboardState::new(layout::gb());
+    kb_state.remap = remap::caps_lock_to_ctrl();
+    kb_state.actuate_key(keys::KEY_CAPS_LOCK, true);
+    assert_eq!(kb_state.sync_pressed(&[keys::KEY_CAPS_LOCK]), vec![]);
+    assert!(kb_state.ctrl() && !kb_state.key_pressed(keys::KEY_CAPS_LOCK));
+    let event = kb_state.actuate_key(keys::KEY_CAPS_LOCK, false);
+    assert_eq!((event.key, event.pressed, event.repeat), (keys::KEY_L_CTRL, false, false));
+    assert!(!kb_state.ctrl());
+
+    // Caps lock was pressed while the state wasn't listening, should press ctrl through the remap
+    let events = kb_state.sync_pressed(&[keys::KEY_CAPS_LOCK, keys::KEY_A]);
+    let changes: Vec<_> = events.iter().map(|event| (event.key, event.pressed)).collect();
+    assert_eq!(changes, vec![(keys::KEY_A, true), (keys::KEY_L_CTRL, true)]);
+    let events = kb_state.sync_pressed(&[keys::KEY_A]);
+    let changes: Vec<_> = events.iter().map(|event| (event.key, event.pressed)).collect();
+    assert_eq!(changes, vec![(keys::KEY_L_CTRL, false)]);
+    assert!(!kb_state.ctrl() && kb_state.key_pressed(keys::KEY_A));
+}
+
+#[test]
+fn test_terminal_encode() {
+    let layout = layout::us();
+    let mut kb_state = KeyboardState::new(layout.clone());
+    let mut modes = TerminalModes::default();
+
+    let press = |kb_state: &mut KeyboardState, key, modes: &TerminalModes| {
+        let event = kb_state.actuate_key(key, true);
+        kb_state.actuate_key(key, false);
+        terminal::encode(&event, &layout, modes)
+    };
+
+    assert_eq!(press(&mut kb_state, keys::KEY_A, &modes), b"a");
+    assert_eq!(press(&mut kb_state, keys::KEY_SPACE, &modes), b" ");
+    assert_eq!(press(&mut kb_state, keys::KEY_UP, &modes), b"\x1B[A");
+    assert_eq!(press(&mut kb_state, keys::KEY_F5, &modes), b"\x1B[15~");
+    assert_eq!(press(&mut kb_state, keys::KEY_BACKSPACE, &modes), b"\x7F");
+
+    // Ctrl+C should send ETX, and Ctrl+Up should have a modifier parameter
+    kb_state.actuate_key(keys::KEY_L_CTRL, true);
+    assert_eq!(press(&mut kb_state, keys::KEY_C, &modes), b"\x03");
+    assert_eq!(press(&mut kb_state, keys::KEY_UP, &modes), b"\x1B[1;5A");
+    assert_eq!(press(&mut kb_state, keys::KEY_BACKSPACE, &modes), b"\x08");
+    kb_state.actuate_key(keys::KEY_L_CTRL, false);
+
+    // Alt should prefix ESC
+    kb_state.actuate_key(keys::KEY_ALT, true);
+    assert_eq!(press(&mut kb_state, keys::KEY_X, &modes), b"\x1Bx");
+    assert_eq!(press(&mut kb_state, keys::KEY_DEL, &modes), b"\x1B[3;3~");
+    kb_state.actuate_key(keys::KEY_ALT, false);
+
+    kb_state.actuate_key(keys::KEY_L_SHIFT, true);
+    assert_eq!(press(&mut kb_state, keys::KEY_TAB, &modes), b"\x1B[Z");
+    kb_state.actuate_key(keys::KEY_L_SHIFT, false);
+
+    // Application cursor and keypad modes
+    modes.cursor_keys_application = true;
+    modes.keypad_application = true;
+    assert_eq!(press(&mut kb_state, keys::KEY_UP, &modes), b"\x1BOA");
+    assert_eq!(press(&mut kb_state, keys::KEY_F1, &modes), b"\x1BOP");
+    kb_state.actuate_key(keys::KEY_NUM_LOCK, true);
+    kb_state.actuate_key(keys::KEY_NUM_LOCK, false);
+    assert_eq!(press(&mut kb_state, keys::KEY_NUM_5, &modes), b"\x1BOu");
+    assert_eq!(press(&mut kb_state, keys::KEY_NUM_ENTER, &modes), b"\x1BOM");
+
+    // Releases don't send anything
+    let event = kb_state.actuate_key(keys::KEY_A, false);
+    assert_eq!(terminal::encode(&event, &layout, &modes), b"");
+}
+
+#[test]
+fn test_terminal_decode() {
+    let mut decoder = TerminalDecoder::new(layout::us());
+    let ms = Duration::from_millis;
+    let logical = |events: Vec<orbkb::event::KeyboardEvent>| -> Vec<_> {
+        events.into_iter().filter(|event| event.pressed).map(|event| (event.key, event.logical)).collect()
+    };
+
+    // Characters should be pressed and released
+    let events = decoder.feed(b"aB", ms(0));
+    assert_eq!(events.len(), 4);
+    assert!(!events[1].pressed);
+    assert_eq!(logical(events), vec![
+        (keys::KEY_A, Some(LogicalKeyboardEvent::Symbol('a'))),
+        (keys::KEY_B, Some(LogicalKeyboardEvent::Symbol('B'))),
+    ]);
+
+    // Ctrl+C, Alt+x, Ctrl+Up and F5
+    assert_eq!(logical(decoder.feed(b"\x03\x1Bx\x1B[1;5A\x1B[15~", ms(0))), vec![
+        (keys::KEY_C, Some(LogicalKeyboardEvent::Command(state::CTRL, keys::KEY_C))),
+        (keys::KEY_X, Some(LogicalKeyboardEvent::Command(state::ALT, keys::KEY_X))),
+        (keys::KEY_UP, Some(LogicalKeyboardEvent::Command(state::CTRL, keys::KEY_UP))),
+        (keys::KEY_F5, Some(LogicalKeyboardEvent::Command(state::Modifiers::empty(), keys::KEY_F5))),
+    ]);
+
+    // SS3 sequences and modifyOtherKeys
+    assert_eq!(logical(decoder.feed(b"\x1BOA\x1BOu\x1B[27;5;13~", ms(0))), vec![
+        (keys::KEY_UP, Some(LogicalKeyboardEvent::Command(state::Modifiers::empty(), keys::KEY_UP))),
+        (keys::KEY_NUM_5, Some(LogicalKeyboardEvent::Symbol('5'))),
+        (keys::KEY_ENTER, Some(LogicalKeyboardEvent::Command(state::CTRL, keys::KEY_ENTER))),
+    ]);
+
+    // A sequence split across reads should be decoded once it is complete
+    assert_eq!(decoder.feed(b"\x1B[", ms(0)), vec![]);
+    assert_eq!(logical(decoder.feed(b"B", ms(10))), vec![
+        (keys::KEY_DOWN, Some(LogicalKeyboardEvent::Command(state::Modifiers::empty(), keys::KEY_DOWN))),
+    ]);
+
+    // A lone ESC should be decoded as the escape key after the timeout
+    assert_eq!(decoder.feed(b"\x1B", ms(100)), vec![]);
+    assert_eq!(decoder.next_deadline(), Some(ms(150)));
+    assert_eq!(decoder.tick(ms(140)), vec![]);
+    assert_eq!(logical(decoder.tick(ms(150))), vec![
+        (keys::KEY_ESC, Some(LogicalKeyboardEvent::Command(state::Modifiers::empty(), keys::KEY_ESC))),
+    ]);
+    assert_eq!(decoder.next_deadline(), None);
+}
+
+#[test]
+fn test_terminal_decode_kitty() {
+    let mut decoder = TerminalDecoder::new(layout::us());
+    decoder.report_event_types = true;
+    let ms = Duration::from_millis;
+
+    // Press, repeat and release 'a' key
+    let events = decoder.feed(b"\x1B[97u\x1B[97;1:2u\x1B[97;1:3u", ms(0));
+    assert_eq!(events.iter().map(|event| (event.key, event.pressed, event.repeat)).collect::<Vec<_>>(), vec![
+        (keys::KEY_A, true, false),
+        (keys::KEY_A, true, true),
+        (keys::KEY_A, false, false),
+    ]);
+    assert_eq!(events[1].logical, Some(LogicalKeyboardEvent::Symbol('a')));
+    assert_eq!(events[2].logical, None);
+
+    // Shift+a with the text reported, and the escape key without a timeout
+    let events = decoder.feed(b"\x1B[97;2;65u\x1B[27u", ms(0));
+    assert_eq!(events[0].logical, Some(LogicalKeyboardEvent::Symbol('A')));
+    assert_eq!(events[1].key, keys::KEY_ESC);
+    assert_eq!(decoder.next_deadline(), None);
+
+    // Ctrl+Shift+Left Ctrl release
+    let events = decoder.feed(b"\x1B[57442;6:3u", ms(0));
+    assert_eq!((events[0].key, events[0].pressed), (keys::KEY_L_CTRL, false));
+}
+
+#[test]
+fn test_unicode_input() {
+    let mut kb_state = KeyboardState::new(layout::gb());
+    kb_state.unicode_input_enabled = true;
+
+    let tap = |kb_state: &mut KeyboardState, key| {
+        let result = kb_state.actuate_key(key, true);
+        kb_state.actuate_key(key, false);
+        result.logical
+    };
+
+    // Press Ctrl+Shift+U, then type "1f600" with Ctrl+Shift still held for the first digit
+    kb_state.actuate_key(keys::KEY_L_CTRL, true);
+    kb_state.actuate_key(keys::KEY_L_SHIFT, true);
+    assert_eq!(tap(&mut kb_state, keys::KEY_U), None);
+    assert_eq!(kb_state.unicode_input(), Some(""));
+    assert_eq!(tap(&mut kb_state, keys::KEY_1), None);
+    kb_state.actuate_key(keys::KEY_L_SHIFT, false);
+    kb_state.actuate_key(keys::KEY_L_CTRL, false);
+    for &key in &[keys::KEY_F, keys::KEY_6, keys::KEY_X, keys::KEY_0, keys::KEY_0] {
+        assert_eq!(tap(&mut kb_state, key), None);
+    }
+    assert_eq!(kb_state.unicode_input(), Some("1f600"));
+
+    // Press space, should emit the symbol
+    assert_eq!(tap(&mut kb_state, keys::KEY_SPACE), Some(LogicalKeyboardEvent::Symbol('\u{1F600}')));
+    assert_eq!(kb_state.unicode_input(), None);
+    assert_eq!(tap(&mut kb_state, keys::KEY_A), Some(LogicalKeyboardEvent::Symbol('a')));
+
+    // Surrogates aren't valid symbols
+    kb_state.actuate_key(keys::KEY_L_CTRL, true);
+    kb_state.actuate_key(keys::KEY_L_SHIFT, true);
+    tap(&mut kb_state, keys::KEY_U);
+    kb_state.actuate_key(keys::KEY_L_SHIFT, false);
+    kb_state.actuate_key(keys::KEY_L_CTRL, false);
+    for &key in &[keys::KEY_D, keys::KEY_8, keys::KEY_0, keys::KEY_0] {
+        tap(&mut kb_state, key);
+    }
+    assert_eq!(tap(&mut kb_state, keys::KEY_ENTER), None);
+    assert_eq!(kb_state.unicode_input(), None);
+
+    // Press escape, should cancel
+    kb_state.actuate_key(keys::KEY_L_CTRL, true);
+    kb_state.actuate_key(keys::KEY_L_SHIFT, true);
+    tap(&mut kb_state, keys::KEY_U);
+    kb_state.actuate_key(keys::KEY_L_SHIFT, false);
+    kb_state.actuate_key(keys::KEY_L_CTRL, false);
+    tap(&mut kb_state, keys::KEY_4);
+    assert_eq!(tap(&mut kb_state, keys::KEY_ESC), None);
+    assert_eq!(kb_state.unicode_input(), None);
+    assert_eq!(tap(&mut kb_state, keys::KEY_4), Some(LogicalKeyboardEvent::Symbol('4')));
+
+    // Press caps lock while typing, should still toggle it and emit its command
+    kb_state.actuate_key(keys::KEY_L_CTRL, true);
+    kb_state.actuate_key(keys::KEY_L_SHIFT, true);
+    tap(&mut kb_state, keys::KEY_U);
+    kb_state.actuate_key(keys::KEY_L_SHIFT, false);
+    kb_state.actuate_key(keys::KEY_L_CTRL, false);
+    tap(&mut kb_state, keys::KEY_A);
+    assert!(matches!(tap(&mut kb_state, keys::KEY_CAPS_LOCK), Some(LogicalKeyboardEvent::Command(_, keys::KEY_CAPS_LOCK))));
+    assert!(kb_state.caps_lock());
+    assert_eq!(kb_state.unicode_input(), Some("a"));
+
+    // Press backspace with no digits left, should cancel
+    assert_eq!(tap(&mut kb_state, keys::KEY_BACKSPACE), None);
+    assert_eq!(kb_state.unicode_input(), Some(""));
+    assert_eq!(tap(&mut kb_state, keys::KEY_BACKSPACE), None);
+    assert_eq!(kb_state.unicode_input(), None);
+    assert!(matches!(tap(&mut kb_state, keys::KEY_BACKSPACE), Some(LogicalKeyboardEvent::Command(_, keys::KEY_BACKSPACE))));
+}
+
+#[test]
+fn test_input_method() {
+    let mut kb_state = KeyboardState::new(layout::us());
+    kb_state.input_method = Some(Box::new(TableInputMethod::pinyin_demo()));
+
+    let tap = |kb_state: &mut KeyboardState, key| {
+        let result = kb_state.actuate_key(key, true);
+        kb_state.actuate_key(key, false);
+        result.logical
+    };
+
+    // Type "ni", should compose with candidates
+    tap(&mut kb_state, keys::KEY_N);
+    assert_eq!(tap(&mut kb_state, keys::KEY_I), Some(LogicalKeyboardEvent::Preedit(Preedit {
+        text: "ni".to_string(),
+        cursor: 2,
+        candidates: vec!["你".to_string(), "尼".to_string(), "泥".to_string()],
+        selected: Some(0),
+    })));
+
+    // Type "hao" and press space, should commit the phrase
+    for &key in &[keys::KEY_H, keys::KEY_A, keys::KEY_O] {
+        tap(&mut kb_state, key);
+    }
+    assert_eq!(tap(&mut kb_state, keys::KEY_SPACE), Some(LogicalKeyboardEvent::Commit {
+        text: "你好".to_string(),
+        preedit: Preedit::default(),
+    }));
+
+    // Type "wen" and press 2, should commit the second candidate
+    for &key in &[keys::KEY_W, keys::KEY_E, keys::KEY_N] {
+        tap(&mut kb_state, key);
+    }
+    assert_eq!(tap(&mut kb_state, keys::KEY_2), Some(LogicalKeyboardEvent::Commit {
+        text: "问".to_string(),
+        preedit: Preedit::default(),
+    }));
+
+    // Nothing is being composed, so keys are handled as usual
+    assert_eq!(tap(&mut kb_state, keys::KEY_1), Some(LogicalKeyboardEvent::Symbol('1')));
+}
+
+#[test]
+fn test_romaji_input_method() {
+    let mut kb_state = KeyboardState::new(layout::us());
+    kb_state.input_method = Some(Box::new(RomajiInputMethod::new()));
+
+    let type_keys = |kb_state: &mut KeyboardState, keys: &[keys::Key]| {
+        let mut text = String::new();
+        for &key in keys {
+            match kb_state.actuate_key(key, true).logical {
+                Some(LogicalKeyboardEvent::Commit { text: ref kana, .. }) => text.push_str(kana),
+                Some(LogicalKeyboardEvent::Symbol(symbol)) => text.push(symbol),
+                _ => (),
+            }
+            kb_state.actuate_key(key, false);
+        }
+        text
+    };
+
+    // Type "konnnichiha", should convert to hiragana
+    assert_eq!(type_keys(&mut kb_state, &[
+        keys::KEY_K, keys::KEY_O, keys::KEY_N, keys::KEY_N, keys::KEY_N, keys::KEY_I,
+        keys::KEY_C, keys::KEY_H, keys::KEY_I, keys::KEY_H, keys::KEY_A,
+    ]), "こんにちは");
+
+    // Type "kitte", should type a small tsu for the double consonant
+    assert_eq!(type_keys(&mut kb_state, &[keys::KEY_K, keys::KEY_I, keys::KEY_T, keys::KEY_T, keys::KEY_E]), "きって");
+
+    // Type "shinbun" and press space, should type ん before a consonant and at the end
+    assert_eq!(type_keys(&mut kb_state, &[
+        keys::KEY_S, keys::KEY_H, keys::KEY_I, keys::KEY_N, keys::KEY_B, keys::KEY_U, keys::KEY_N, keys::KEY_SPACE,
+    ]), "しんぶん");
+
+    // Press Katakana/Hiragana and type "ka-do", should convert to katakana
+    assert_eq!(type_keys(&mut kb_state, &[
+        keys::KEY_KATAKANA_HIRAGANA, keys::KEY_K, keys::KEY_A, keys::KEY_HYPHEN, keys::KEY_D, keys::KEY_O,
+    ]), "カード");
+
+    // Press Muhenkan, should type letters as they are
+    assert_eq!(type_keys(&mut kb_state, &[keys::KEY_MUHENKAN, keys::KEY_K, keys::KEY_A]), "ka");
+    assert_eq!(type_keys(&mut kb_state, &[keys::KEY_HENKAN, keys::KEY_K, keys::KEY_A]), "カ");
+
+    // Type "fairu" and "vaiorin", should use extended kana
+    assert_eq!(type_keys(&mut kb_state, &[keys::KEY_F, keys::KEY_A, keys::KEY_I, keys::KEY_R, keys::KEY_U]), "ファイル");
+    assert_eq!(type_keys(&mut kb_state, &[
+        keys::KEY_V, keys::KEY_A, keys::KEY_I, keys::KEY_O, keys::KEY_R, keys::KEY_I, keys::KEY_N, keys::KEY_SPACE,
+    ]), "ヴァイオリン");
+    assert_eq!(type_keys(&mut kb_state, &[keys::KEY_P, keys::KEY_A, keys::KEY_T, keys::KEY_I, keys::KEY_I]), "パティイ");
+
+    // Type "xtsu", "ltu" and "xya", should type small kana
+    assert_eq!(type_keys(&mut kb_state, &[keys::KEY_KATAKANA_HIRAGANA, keys::KEY_X, keys::KEY_T, keys::KEY_S, keys::KEY_U]), "っ");
+    assert_eq!(type_keys(&mut kb_state, &[keys::KEY_L, keys::KEY_T, keys::KEY_U, keys::KEY_X, keys::KEY_Y, keys::KEY_A]), "っゃ");
+    assert_eq!(type_keys(&mut kb_state, &[keys::KEY_S, keys::KEY_H, keys::KEY_E, keys::KEY_W, keys::KEY_I]), "しぇうぃ");
+}
+
+#[test]
+fn test_privileged_keys() {
+    let mut kb_state = KeyboardState::new(layout::us());
+
+    assert_eq!(scancode::scancode_to_key(false, 0x54), Some(keys::KEY_SYS_RQ));
+
+    // Press Ctrl+Alt+Delete with privileged keys disabled by default, should emit a command
+    kb_state.actuate_key(keys::KEY_L_CTRL, true);
+    kb_state.actuate_key(keys::KEY_ALT, true);
+    assert_eq!(kb_state.actuate_key(keys::KEY_DEL, true).logical,
+               Some(LogicalKeyboardEvent::Command(state::CTRL | state::L_CTRL | state::ALT | state::L_ALT, keys::KEY_DEL)));
+    kb_state.actuate_key(keys::KEY_DEL, false);
+
+    // Enable privileged keys, should report a secure attention event instead of a command
+    kb_state.privileged_keys_enabled = true;
+    let result = kb_state.actuate_key(keys::KEY_DEL, true);
+    assert_eq!((result.logical, result.privileged), (None, Some(PrivilegedEvent::SecureAttention)));
+    assert_eq!(kb_state.actuate_key(keys::KEY_DEL, true).logical,
+               Some(LogicalKeyboardEvent::Command(state::CTRL | state::L_CTRL | state::ALT | state::L_ALT, keys::KEY_DEL)));
+    kb_state.actuate_key(keys::KEY_DEL, false);
+    kb_state.actuate_key(keys::KEY_L_CTRL, false);
+
+    // Press Alt+SysRq+B, should report a magic SysRq event
+    kb_state.actuate_key(keys::KEY_SYS_RQ, true);
+    let result = kb_state.actuate_key(keys::KEY_B, true);
+    assert_eq!((result.logical, result.privileged), (None, Some(PrivilegedEvent::SysRq(keys::KEY_B))));
+    kb_state.actuate_key(keys::KEY_B, false);
+    kb_state.actuate_key(keys::KEY_SYS_RQ, false);
+
+    // Release SysRq, should emit a command again
+    assert_eq!(kb_state.actuate_key(keys::KEY_B, true).logical,
+               Some(LogicalKeyboardEvent::Command(state::ALT | state::L_ALT, keys::KEY_B)));
+}
+
+#[test]
+fn test_keyboard_devices() {
+    let laptop = DeviceId(0);
+    let external = DeviceId(1);
+    let mut devices = KeyboardDevices::new(layout::us());
+
+    // Hold shift on the laptop and type on the external keyboard, should emit 'A'
+    devices.actuate_key(laptop, keys::KEY_L_SHIFT, true);
+    let event = devices.actuate_key(external, keys::KEY_A, true).unwrap();
+    assert_eq!(event.device, external);
+    assert_eq!(event.event.logical, Some(LogicalKeyboardEvent::Symbol('A')));
+    devices.actuate_key(external, keys::KEY_A, false);
+
+    // Hold left shift on both, release it on one, should still be shifted
+    devices.actuate_key(external, keys::KEY_L_SHIFT, true);
+    assert_eq!(devices.actuate_key(laptop, keys::KEY_L_SHIFT, false), None);
+    assert_eq!(devices.modifiers(), state::SHIFT | state::L_SHIFT);
+
+    // Hold 'a' on the laptop and press it on the external keyboard, shouldn't press it again
+    devices.actuate_key(laptop, keys::KEY_A, true);
+    assert_eq!(devices.actuate_key(external, keys::KEY_A, true), None);
+    assert!(devices.actuate_key(laptop, keys::KEY_A, true).unwrap().event.repeat);
+    assert_eq!(devices.actuate_key(laptop, keys::KEY_A, false), None);
+    assert!(!devices.actuate_key(external, keys::KEY_A, false).unwrap().event.pressed);
+
+    // Unplug the external keyboard, should release its keys
+    let events = devices.remove_device(external);
+    assert_eq!(events.len(), 1);
+    assert_eq!((events[0].event.key, events[0].event.pressed), (keys::KEY_L_SHIFT, false));
+    assert_eq!(devices.modifiers(), state::Modifiers::empty());
+    assert_eq!(devices.devices(), vec![laptop]);
+
+    // Use separate lock keys, should only toggle caps lock on one device
+    devices.shared_lock_keys = false;
+    devices.add_device(external);
+    devices.actuate_key(laptop, keys::KEY_CAPS_LOCK, true);
+    devices.actuate_key(laptop, keys::KEY_CAPS_LOCK, false);
+    assert_eq!(devices.lock_keys(laptop), Some(state::CAPS_LOCK));
+    assert_eq!(devices.lock_keys(external), Some(state::LockKeyState::empty()));
+    assert_eq!(devices.actuate_key(external, keys::KEY_A, true).unwrap().event.logical,
+               Some(LogicalKeyboardEvent::Symbol('a')));
+    assert_eq!(devices.actuate_key(laptop, keys::KEY_B, true).unwrap().event.logical,
+               Some(LogicalKeyboardEvent::Symbol('B')));
+
+    // Plug in another device, shouldn't inherit the laptop's caps lock
+    devices.add_device(DeviceId(2));
+    assert_eq!(devices.lock_keys(DeviceId(2)), Some(state::LockKeyState::empty()));
+}
+
+#[test]
+fn test_keyboard_devices_remap() {
+    let laptop = DeviceId(0);
+    let external = DeviceId(1);
+    let mut devices = KeyboardDevices::new(layout::us());
+    devices.state.remap = remap::caps_lock_to_ctrl();
+
+    // Hold caps lock as ctrl on the laptop and unplug the external keyboard, should stay held
+    devices.actuate_key(laptop, keys::KEY_CAPS_LOCK, true);
+    devices.actuate_key(external, keys::KEY_A, true);
+    devices.actuate_key(laptop, keys::KEY_A, true);
+    assert_eq!(devices.remove_device(external), vec![]);
+    assert!(devices.state.ctrl());
+    assert!(devices.state.key_pressed(keys::KEY_A));
+
+    // Unplug the laptop, should release ctrl and 'a'
+    let events = devices.remove_device(laptop);
+    let changes: Vec<_> = events.iter().map(|event| (event.event.key, event.event.pressed)).collect();
+    assert_eq!(changes, vec![(keys::KEY_A, false), (keys::KEY_L_CTRL, false)]);
+    assert!(!devices.state.ctrl());
+}
+
+#[test]
+fn test_timestamps() {
+    let mut kb_state = KeyboardState::new(layout::us());
+    let ms = Duration::from_millis;
+
+    // Press 'a' at 100ms, should remember when it was pressed
+    let event = kb_state.actuate_key_at(keys::KEY_A, true, ms(100));
+    assert_eq!((event.timestamp, event.held_for), (Some(ms(100)), None));
+    assert_eq!(kb_state.pressed_at(keys::KEY_A), Some(ms(100)));
+
+    // Auto-repeat at 600ms, shouldn't change the press time
+    let event = kb_state.actuate_key_at(keys::KEY_A, true, ms(600));
+    assert!(event.repeat);
+    assert_eq!(kb_state.pressed_at(keys::KEY_A), Some(ms(100)));
+
+    // Release 'a' at 750ms, should have been held for 650ms
+    let event = kb_state.actuate_key_at(keys::KEY_A, false, ms(750));
+    assert_eq!((event.timestamp, event.held_for), (Some(ms(750)), Some(ms(650))));
+    assert_eq!(kb_state.pressed_at(keys::KEY_A), None);
+
+    // Press and release without times, shouldn't have any
+    let event = kb_state.actuate_key(keys::KEY_B, true);
+    assert_eq!((event.timestamp, kb_state.pressed_at(keys::KEY_B)), (None, None));
+    let event = kb_state.actuate_key_at(keys::KEY_B, false, ms(900));
+    assert_eq!((event.timestamp, event.held_for), (Some(ms(900)), None));
 }